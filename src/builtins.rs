@@ -0,0 +1,600 @@
+use crate::{
+    error::ParserError,
+    math::{
+        eval_binary, expect_function, expect_list, expect_number, BinaryOp, Literal, Number, Scope,
+    },
+    matrix,
+    session::Settings,
+    stats,
+};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::FromPrimitive;
+use std::f64::consts;
+
+/// Value of the builtin constant `name`. Constants are available in every session
+/// and can only be redefined with `let shadow`.
+pub fn constant(name: &str) -> Option<Number> {
+    match name {
+        "pi" => Some(Number::F64(consts::PI)),
+        "e" => Some(Number::F64(consts::E)),
+        "tau" => Some(Number::F64(consts::TAU)),
+        // The golden ratio, (1 + sqrt(5)) / 2.
+        "phi" => Some(Number::F64(1.618_033_988_749_895)),
+        "inf" => Some(Number::F64(f64::INFINITY)),
+        "nan" => Some(Number::F64(f64::NAN)),
+        _ => None,
+    }
+}
+/// Upper bound on the number of calls `sum` and `table` make, so that a typo in
+/// a bound doesn't hang the REPL.
+const MAX_ITERATIONS: usize = 1_000_000;
+
+/// Calls the builtin function `name` with already evaluated arguments. The higher
+/// order functions call their function argument in `scope`, the others only take
+/// numbers.
+pub fn call_builtin(name: &str, args: Vec<Literal>, scope: &Scope) -> Result<Literal, ParserError> {
+    let context = format!("Function {:#?}", name);
+    match name {
+        // `map(f, [1, 2, 3])` calls `f` on each item.
+        "map" => {
+            check_arity(name, &args, 2)?;
+            let mut args = args.into_iter();
+            let function = expect_function(args.next().unwrap(), &context)?;
+            expect_list(args.next().unwrap(), &context)?
+                .into_iter()
+                .map(|item| scope.call(&function, vec![item]))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Literal::List)
+        }
+        // `fold(f, init, list)` calls `f(acc, item)` from left to right.
+        "fold" => {
+            check_arity(name, &args, 3)?;
+            let mut args = args.into_iter();
+            let function = expect_function(args.next().unwrap(), &context)?;
+            let init = args.next().unwrap();
+            expect_list(args.next().unwrap(), &context)?
+                .into_iter()
+                .try_fold(init, |acc, item| scope.call(&function, vec![acc, item]))
+        }
+        // `sum(f, a, b)` adds `f(i)` for the integers `i` from `a` to `b` included,
+        // `prod(f, a, b)` multiplies them. The `sum(i, a, b, body)` notation is
+        // handled by `eval`, as `body` can't be evaluated before `i` is bound.
+        // With a single list, `sum` and `prod` add or multiply its items.
+        "sum" | "prod" if args.len() == 1 => {
+            let mut items = expect_list(args.into_iter().next().unwrap(), &context)?.into_iter();
+            let (op, identity) = match name {
+                "prod" => (BinaryOp::Mul, Literal::Num(Number::I32(1))),
+                _ => (BinaryOp::Add, Literal::Num(Number::I32(0))),
+            };
+            // Starting from the first item rather than from the identity, so that
+            // strict mode only sees the items.
+            let mut total = items.next().unwrap_or(identity);
+            for item in items {
+                total = eval_binary(op, total, item, scope.settings())?;
+            }
+            Ok(total)
+        }
+        "sum" | "prod" => {
+            check_arity(name, &args, 3)?;
+            let mut args = args.into_iter();
+            let function = expect_function(args.next().unwrap(), &context)?;
+            let (from, to) = (args.next().unwrap(), args.next().unwrap());
+            series(name, from, to, scope.settings(), |index| {
+                scope.call(&function, vec![index])
+            })
+        }
+        // `table(f, start, end, step)` lists the `[x, f(x)]` pairs for x going from
+        // `start` to `end` included. Each x is computed from `start` rather than
+        // accumulated, and `end` is included despite rounding errors.
+        "table" => {
+            check_arity(name, &args, 4)?;
+            let mut args = args.into_iter();
+            let function = expect_function(args.next().unwrap(), &context)?;
+            let mut bounds = vec![];
+            for arg in args {
+                bounds.push(real(name, &expect_number(arg, &context)?)?);
+            }
+            let (start, end, step) = (bounds[0], bounds[1], bounds[2]);
+            if step == 0.0 || !step.is_finite() {
+                return Err(ParserError::newr(
+                    "".to_string(),
+                    format!("{} expects a finite, non zero step, found {}", name, step),
+                ));
+            }
+            let steps = ((end - start) / step + 1e-9).floor();
+            if steps.is_nan() || steps >= MAX_ITERATIONS as f64 {
+                return Err(too_many_iterations(name));
+            }
+            let mut rows = vec![];
+            for i in 0..(steps.max(-1.0) + 1.0) as usize {
+                let x = Literal::Num(Number::F64(start + i as f64 * step));
+                let y = scope.call(&function, vec![x.clone()])?;
+                rows.push(Literal::List(vec![x, y]));
+            }
+            Ok(Literal::List(rows))
+        }
+        "dot" => {
+            check_arity(name, &args, 2)?;
+            let mut args = args.into_iter();
+            let u = expect_list(args.next().unwrap(), &context)?;
+            let v = expect_list(args.next().unwrap(), &context)?;
+            matrix::dot(u, v, scope.settings())
+        }
+        "transpose" => {
+            check_arity(name, &args, 1)?;
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            matrix::transpose(items).map(Literal::List)
+        }
+        "det" => {
+            check_arity(name, &args, 1)?;
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            matrix::det(items, &scope.settings().lenient())
+        }
+        "inv" => {
+            check_arity(name, &args, 1)?;
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            matrix::inverse(items, &scope.settings().lenient())
+        }
+        "count" => {
+            check_arity(name, &args, 1)?;
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            Ok(Literal::Num(Number::from_bigint(
+                items.len().into(),
+                32,
+                false,
+            )))
+        }
+        "mean" | "median" | "mode" | "variance" | "stddev" => {
+            check_arity(name, &args, 1)?;
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            let samples = stats::samples(name, items)?;
+            let settings = &scope.settings().lenient();
+            match name {
+                "mean" => stats::mean(samples, settings),
+                "median" => stats::median(samples, settings),
+                "mode" => stats::mode(samples),
+                "variance" => stats::variance(samples, settings),
+                _ => call_numeric("sqrt", vec![stats::variance(samples, settings)?]),
+            }
+            .map(Literal::Num)
+        }
+        "percentile" => {
+            check_arity(name, &args, 2)?;
+            let mut args = args.into_iter();
+            let items = expect_list(args.next().unwrap(), &context)?;
+            let p = expect_number(args.next().unwrap(), &context)?;
+            let samples = stats::samples(name, items)?;
+            stats::percentile(samples, p, &scope.settings().lenient()).map(Literal::Num)
+        }
+        "linreg" => {
+            check_arity(name, &args, 2)?;
+            let mut args = args.into_iter();
+            let xs = stats::samples(name, expect_list(args.next().unwrap(), &context)?)?;
+            let ys = stats::samples(name, expect_list(args.next().unwrap(), &context)?)?;
+            stats::linreg(xs, ys)
+        }
+        // `min` and `max` also take a single list.
+        "min" | "max" if matches!(&args[..], [Literal::List(_)]) => {
+            let items = expect_list(args.into_iter().next().unwrap(), &context)?;
+            call_numeric(name, stats::samples(name, items)?).map(Literal::Num)
+        }
+        _ => {
+            let args = args
+                .into_iter()
+                .map(|arg| expect_number(arg, &context))
+                .collect::<Result<Vec<_>, _>>()?;
+            call_numeric(name, args).map(Literal::Num)
+        }
+    }
+}
+/// Adds, for `sum`, or multiplies, for `prod`, the terms computed for each
+/// integer from `from` to `to` included. An empty range gives 0 or 1.
+pub fn series(
+    name: &str,
+    from: Literal,
+    to: Literal,
+    settings: &Settings,
+    mut term: impl FnMut(Literal) -> Result<Literal, ParserError>,
+) -> Result<Literal, ParserError> {
+    let context = format!("Function {:#?}", name);
+    let from = expect_integer(from, &context)?;
+    let to = expect_integer(to, &context)?;
+    if &to - &from >= BigInt::from(MAX_ITERATIONS) {
+        return Err(too_many_iterations(name));
+    }
+    let (op, mut total) = match name {
+        "prod" => (BinaryOp::Mul, Literal::Num(Number::I32(1))),
+        _ => (BinaryOp::Add, Literal::Num(Number::I32(0))),
+    };
+    let mut i = from;
+    while i <= to {
+        let index = Literal::Num(Number::from_bigint(i.clone(), 32, false));
+        total = eval_binary(op, total, term(index)?, settings)?;
+        i += 1;
+    }
+    Ok(total)
+}
+fn call_numeric(name: &str, args: Vec<Number>) -> Result<Number, ParserError> {
+    match name {
+        "float" => {
+            check_arity(name, &args, 1)?;
+            match &args[0] {
+                Number::Complex(_) => Err(ParserError::newr(
+                    "".to_string(),
+                    format!("Cannot convert {} to a float", args[0]),
+                )),
+                num => Ok(Number::F64(num.as_f64())),
+            }
+        }
+        // The square root of a negative number is imaginary, and so on for the other
+        // functions outside of their real domain.
+        "sqrt" => real_or_complex(name, &args, f64::sqrt, Complex64::sqrt, |x| x >= 0.0),
+        "exp" => real_or_complex(name, &args, f64::exp, Complex64::exp, |_| true),
+        "ln" => real_or_complex(name, &args, f64::ln, Complex64::ln, |x| x >= 0.0),
+        "log10" => real_or_complex(name, &args, f64::log10, Complex64::log10, |x| x >= 0.0),
+        "log2" => real_or_complex(name, &args, f64::log2, Complex64::log2, |x| x >= 0.0),
+        "sin" => real_or_complex(name, &args, f64::sin, Complex64::sin, |_| true),
+        "cos" => real_or_complex(name, &args, f64::cos, Complex64::cos, |_| true),
+        "tan" => real_or_complex(name, &args, f64::tan, Complex64::tan, |_| true),
+        "asin" => real_or_complex(name, &args, f64::asin, Complex64::asin, |x| x.abs() <= 1.0),
+        "acos" => real_or_complex(name, &args, f64::acos, Complex64::acos, |x| x.abs() <= 1.0),
+        "atan" => real_or_complex(name, &args, f64::atan, Complex64::atan, |_| true),
+        "atan2" => {
+            check_arity(name, &args, 2)?;
+            let (y, x) = (real(name, &args[0])?, real(name, &args[1])?);
+            Ok(Number::F64(y.atan2(x)))
+        }
+        "hypot" => {
+            check_arity(name, &args, 2)?;
+            let (x, y) = (real(name, &args[0])?, real(name, &args[1])?);
+            Ok(Number::F64(x.hypot(y)))
+        }
+        // The absolute value of a complex number is its modulus.
+        "abs" => {
+            check_arity(name, &args, 1)?;
+            match args.into_iter().next().unwrap() {
+                Number::Complex(num) => Ok(Number::F64(num.norm())),
+                // Unlike a comparison with 0, this also turns `-0.0` into `0.0`.
+                Number::F64(num) => Ok(Number::F64(num.abs())),
+                num if num.compare(BinaryOp::Lt, &Number::I32(0))? => -num,
+                num => Ok(num),
+            }
+        }
+        "floor" => to_integer(name, &args, f64::floor, BigRational::floor),
+        "ceil" => to_integer(name, &args, f64::ceil, BigRational::ceil),
+        // Halfway cases are rounded away from zero.
+        "round" => to_integer(name, &args, f64::round, BigRational::round),
+        "min" => extremum(name, args, BinaryOp::Lt),
+        "max" => extremum(name, args, BinaryOp::Gt),
+        _ => Err(ParserError::newr(
+            "".to_string(),
+            format!("Unknown function: {:#?}", name),
+        )),
+    }
+}
+/// Applies a function of one real argument, or its complex counterpart when the
+/// argument is complex or outside of `domain`.
+fn real_or_complex(
+    name: &str,
+    args: &[Number],
+    real: fn(f64) -> f64,
+    complex: fn(Complex64) -> Complex64,
+    domain: fn(f64) -> bool,
+) -> Result<Number, ParserError> {
+    check_arity(name, args, 1)?;
+    match &args[0] {
+        Number::Complex(num) => Ok(Number::Complex(complex(*num))),
+        num if !domain(num.as_f64()) => {
+            Ok(Number::Complex(complex(Complex64::new(num.as_f64(), 0.0))))
+        }
+        num => Ok(Number::F64(real(num.as_f64()))),
+    }
+}
+/// `floor`, `ceil` and `round` give integers, except for infinite floats and nan.
+fn to_integer(
+    name: &str,
+    args: &[Number],
+    float: fn(f64) -> f64,
+    rational: fn(&BigRational) -> BigRational,
+) -> Result<Number, ParserError> {
+    check_arity(name, args, 1)?;
+    let integer = match &args[0] {
+        Number::F64(num) => match BigInt::from_f64(float(*num)) {
+            Some(integer) => integer,
+            None => return Ok(Number::F64(*num)),
+        },
+        Number::Rational(num) => rational(num).to_integer(),
+        Number::Decimal(num) => rational(&num.to_rational()).to_integer(),
+        Number::Complex(_) => return Err(not_real(name, &args[0])),
+        num => return Ok(num.clone()),
+    };
+    Ok(Number::from_bigint(integer, 32, false))
+}
+/// `min` and `max` take any number of arguments and return one of them unchanged.
+fn extremum(name: &str, args: Vec<Number>, op: BinaryOp) -> Result<Number, ParserError> {
+    let mut args = args.into_iter();
+    let mut extremum = args.next().ok_or_else(|| {
+        ParserError::newr(
+            "".to_string(),
+            format!("{} expects at least 1 argument(s), found 0", name),
+        )
+    })?;
+    for arg in args {
+        if arg.compare(op, &extremum)? {
+            extremum = arg;
+        }
+    }
+    Ok(extremum)
+}
+fn expect_integer(value: Literal, context: &str) -> Result<BigInt, ParserError> {
+    match expect_number(value, context)? {
+        num if num.is_integer() => Ok(num.as_bigint()),
+        num => Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects an integer, found {}", context, num),
+        )),
+    }
+}
+fn too_many_iterations(name: &str) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!("{} can't make more than {} calls", name, MAX_ITERATIONS),
+    )
+}
+fn real(name: &str, num: &Number) -> Result<f64, ParserError> {
+    match num {
+        Number::Complex(_) => Err(not_real(name, num)),
+        num => Ok(num.as_f64()),
+    }
+}
+fn not_real(name: &str, num: &Number) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!("{} doesn't apply to complex numbers, found {}", name, num),
+    )
+}
+fn check_arity<T>(name: &str, args: &[T], arity: usize) -> Result<(), ParserError> {
+    if args.len() == arity {
+        Ok(())
+    } else {
+        Err(ParserError::newr(
+            "".to_string(),
+            format!(
+                "{} expects {} argument(s), found {}",
+                name,
+                arity,
+                args.len()
+            ),
+        ))
+    }
+}
+
+mod test {
+    use crate::error::ParserError;
+    use crate::eval_line;
+    use crate::math::{Literal, Number};
+    use crate::session::Session;
+    use num_bigint::BigInt;
+    use num_complex::Complex64;
+
+    #[test]
+    fn float() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_line("float(3)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(3.))))
+        );
+        assert!(eval_line("float(1, 2)".to_string(), &mut session).is_err());
+        assert!(eval_line("frobnicate(1)".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn constants() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("pi", std::f64::consts::PI),
+            ("e", std::f64::consts::E),
+            ("tau / 2", std::f64::consts::PI),
+            ("phi", (1. + 5f64.sqrt()) / 2.),
+            ("-inf", f64::NEG_INFINITY),
+        ];
+        for (line, result) in cases {
+            assert_eq!(
+                eval_line(line.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(Number::F64(result)))),
+                "{}",
+                line
+            );
+        }
+        let (_, nan) = eval_line("nan".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", nan), "NaN");
+        assert_eq!(
+            eval_line("nan == nan".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Bool(false)))
+        );
+        assert_eq!(
+            eval_line("let pi = 3;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "let pi = 3;".to_string(),
+                "\"pi\" is a constant, use `let shadow pi = ...;` to redefine it".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("let shadow pi = 3;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_line("pi * 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(6))))
+        );
+        assert_eq!(
+            eval_line("let shadow = e > 2;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Bool(true)))
+        );
+        assert!(Session::new().variables.is_empty());
+    }
+    #[test]
+    fn math_functions() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("abs(-3)", Number::I32(3)),
+            ("abs(-2.5)", Number::F64(2.5)),
+            ("abs(3 - 4i)", Number::F64(5.)),
+            ("abs(-inf)", Number::F64(f64::INFINITY)),
+            ("floor(2.7)", Number::I32(2)),
+            ("floor(-2.5)", Number::I32(-3)),
+            ("ceil(2.1)", Number::I32(3)),
+            ("round(2.5)", Number::I32(3)),
+            ("round(-2.5)", Number::I32(-3)),
+            ("floor(7)", Number::I32(7)),
+            ("floor(1e20)", Number::BigInt(BigInt::from(10).pow(20))),
+            ("min(3, 1.5, 2)", Number::F64(1.5)),
+            ("max(3, 1.5, 2)", Number::I32(3)),
+            ("max(-1)", Number::I32(-1)),
+            ("sqrt(16)", Number::F64(4.)),
+            ("exp(0)", Number::F64(1.)),
+            ("ln(1)", Number::F64(0.)),
+            ("log10(1000)", Number::F64(3.)),
+            ("log2(8)", Number::F64(3.)),
+            ("sin(0)", Number::F64(0.)),
+            ("cos(0)", Number::F64(1.)),
+            ("tan(0)", Number::F64(0.)),
+            ("asin(1)", Number::F64(std::f64::consts::FRAC_PI_2)),
+            ("acos(1)", Number::F64(0.)),
+            ("atan(1)", Number::F64(std::f64::consts::FRAC_PI_4)),
+            (
+                "atan2(1, -1)",
+                Number::F64(3. * std::f64::consts::FRAC_PI_4),
+            ),
+            ("hypot(3, 4)", Number::F64(5.)),
+            ("sqrt(abs(-9)) + 1", Number::F64(4.)),
+        ];
+        for (line, result) in cases {
+            assert_eq!(
+                eval_line(line.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                line
+            );
+        }
+        assert_eq!(
+            eval_line("ln(-1)".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(0., std::f64::consts::PI)))
+            ))
+        );
+        assert_eq!(
+            eval_line("atan2(1)".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "atan2 expects 2 argument(s), found 1".to_string()
+            ))
+        );
+        let (_, zero) = eval_line("abs(-0.0)".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", zero), "0.0");
+        assert!(matches!(zero, Literal::Num(Number::F64(num)) if num.is_sign_positive()));
+        assert!(eval_line("min()".to_string(), &mut session).is_err());
+        assert!(eval_line("sin(1, 2)".to_string(), &mut session).is_err());
+        assert!(eval_line("floor(1i)".to_string(), &mut session).is_err());
+        assert!(eval_line("hypot(1i, 1)".to_string(), &mut session).is_err());
+        assert!(eval_line("max(1i, 2)".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn higher_order() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("map(x -> x^2, [1, 2, 3])", "[1, 4, 9]"),
+            ("map(x -> x * 2, [])", "[]"),
+            ("fold((acc, x) -> acc + x, 0, [1, 2, 3, 4])", "10"),
+            ("fold((acc, x) -> acc * 10 + x, 0, [1, 2, 3])", "123"),
+            ("sum(x -> x^2, 1, 10)", "385"),
+            ("sum(x -> 1 / x, 1, 2)", "1.5"),
+            ("sum(x -> x, 5, 1)", "0"),
+            (
+                "table(x -> x^2, 0, 1, 0.5)",
+                "[[0.0, 0.0], [0.5, 0.25], [1.0, 1.0]]",
+            ),
+            (
+                "table(x -> x, 1, 0, -0.5)",
+                "[[1.0, 1.0], [0.5, 0.5], [0.0, 0.0]]",
+            ),
+            ("table(x -> x, 1, 0, 0.5)", "[]"),
+        ];
+        for (line, result) in cases {
+            let (_, value) = eval_line(line.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", value), result, "{}", line);
+        }
+        match eval_line("table(x -> x, 0, 1, 0.1)".to_string(), &mut session) {
+            Ok((_, Literal::List(rows))) => assert_eq!(rows.len(), 11),
+            result => panic!("{:?}", result),
+        }
+        eval_line("let sq = x -> x * x;".to_string(), &mut session).unwrap();
+        eval_line("fn cube(x) = x * x * x;".to_string(), &mut session).unwrap();
+        let (_, value) = eval_line("map(cube, map(sq, [1, 2]))".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", value), "[1, 64]");
+        assert_eq!(
+            eval_line("map(1, [1])".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Function \"map\" expects a function, found 1".to_string()
+            ))
+        );
+        assert!(eval_line("map(sq, 1)".to_string(), &mut session).is_err());
+        assert!(eval_line("map((a, b) -> a, [1])".to_string(), &mut session).is_err());
+        assert!(eval_line("sum(sq, 1.5, 2)".to_string(), &mut session).is_err());
+        assert!(eval_line("sum(sq, 1, 10000000)".to_string(), &mut session).is_err());
+        assert!(eval_line("table(sq, 0, 1, 0)".to_string(), &mut session).is_err());
+        assert!(eval_line("sqrt([1])".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn series() {
+        let mut session = Session::new();
+        eval_line("let i = 7;".to_string(), &mut session).unwrap();
+        eval_line("let n = 5;".to_string(), &mut session).unwrap();
+        let cases = vec![
+            ("sum(i, 1, 100, i^2)", Number::I32(338350)),
+            ("prod(k, 1, n, k)", Number::I32(120)),
+            ("prod(k, 1, 0, k)", Number::I32(1)),
+            ("sum(k, 3, 2, k)", Number::I32(0)),
+            ("sum(i, 1, 3, i) + i", Number::I32(13)),
+            ("sum(i, 1, 3, sum(j, 1, i, j))", Number::I32(10)),
+            ("sum(k, 1, 2, k / 2)", Number::F64(1.5)),
+            (
+                "prod(k, 1, 25, k)",
+                Number::BigInt(BigInt::from(15511210043330985984000000u128)),
+            ),
+            ("prod(x -> x + 1, 0, 3)", Number::I32(24)),
+        ];
+        for (line, result) in cases {
+            assert_eq!(
+                eval_line(line.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                line
+            );
+        }
+        assert_eq!(
+            session.variables.get("i"),
+            Some(&Literal::Num(Number::I32(7)))
+        );
+        assert!(!session.variables.contains_key("k"));
+        // The index can be captured by a lambda, and bounds can be parameters.
+        let (_, value) = eval_line(
+            "sum(i, 1, 2, fold((acc, x) -> acc + x * i, 0, [1, 2]))".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(format!("{}", value), "9");
+        let (_, value) = eval_line(
+            "map(k -> sum(j, 1, k, j), [1, 2, 3])".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(format!("{}", value), "[1, 3, 6]");
+        assert!(eval_line("sum(i, 1, 2.5, i)".to_string(), &mut session).is_err());
+        assert!(eval_line("sum(i, 1, 10000000, i)".to_string(), &mut session).is_err());
+        assert!(eval_line("sum(1, 1, 3, 2)".to_string(), &mut session).is_err());
+    }
+}
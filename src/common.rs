@@ -1,5 +1,4 @@
 use crate::error::ParserError;
-use crate::math::Atom;
 pub fn take_while1<V, X, T: Fn(String) -> Result<(X, V), ParserError>>(
     s: String,
     predicate: T,
@@ -14,7 +13,7 @@ pub fn take_while1<V, X, T: Fn(String) -> Result<(X, V), ParserError>>(
                     chars.next();
                 }
                 Err(_) => {
-                    if results.len() == 0 {
+                    if results.is_empty() {
                         return Err(ParserError::new(s));
                     } else {
                         break;
@@ -22,7 +21,7 @@ pub fn take_while1<V, X, T: Fn(String) -> Result<(X, V), ParserError>>(
                 }
             },
             None => {
-                if results.len() == 0 {
+                if results.is_empty() {
                     return Err(ParserError::new(s));
                 } else {
                     break;
@@ -36,14 +35,30 @@ pub fn take_identifier(s: String) -> Result<(String, String), ParserError> {
     let mut identifier = String::new();
     take_alpha(s).and_then(|(remaining, c)| {
         identifier.push(c);
-        take_while0(remaining, |x| take_alphanumeric(x))
-            .and_then(|(remaining, vec)| {
+        take_while0(remaining, take_alphanumeric)
+            .map(|(remaining, vec)| {
                 vec.iter().for_each(|c| identifier.push(*c));
-                Ok((remaining, identifier))
+                (remaining, identifier)
             })
             .and_then(|(remaining, result)| Ok((take_whitespaces0(remaining)?.0, result)))
     })
 }
+/// Words of the grammar, which can't name a variable, a function or a parameter.
+/// `shadow` isn't one of them, as it is only a keyword right after `let`.
+pub const RESERVED: [&str; 12] = [
+    "true", "false", "and", "or", "not", "xor", "if", "then", "else", "let", "fn", "as",
+];
+/// Like `take_identifier`, but the reserved words are rejected.
+pub fn take_name(s: String) -> Result<(String, String), ParserError> {
+    let (remaining, name) = take_identifier(s.clone())?;
+    if RESERVED.contains(&name.as_str()) {
+        return Err(ParserError::newr(
+            s,
+            format!("{:#?} is a reserved word", name),
+        ));
+    }
+    Ok((remaining, name))
+}
 
 pub fn take_while0<V, X, T: Fn(String) -> Result<(X, V), ParserError>>(
     s: String,
@@ -57,7 +72,7 @@ pub fn take_alphanumeric(s: String) -> Result<(String, char), ParserError> {
     first
         .filter(|x| x.is_alphanumeric())
         .ok_or_else(|| ParserError::new(s.clone()))
-        .and_then(|x| Ok((chars.collect::<String>(), x)))
+        .map(|x| (chars.collect::<String>(), x))
 }
 pub fn take_alpha(s: String) -> Result<(String, char), ParserError> {
     let mut chars = s.chars();
@@ -65,7 +80,7 @@ pub fn take_alpha(s: String) -> Result<(String, char), ParserError> {
     first
         .filter(|x| x.is_alphabetic())
         .ok_or_else(|| ParserError::new(s.clone()))
-        .and_then(|x| Ok((chars.collect::<String>(), x)))
+        .map(|x| (chars.collect::<String>(), x))
 }
 
 fn take_ws(s: String) -> Result<(String, char), ParserError> {
@@ -77,10 +92,10 @@ fn take_ws(s: String) -> Result<(String, char), ParserError> {
 }
 #[allow(dead_code)]
 pub fn take_whitespaces1(s: String) -> Result<(String, ()), ParserError> {
-    take_while1(s, |x| take_ws(x)).and_then(|(remaining, _)| Ok((remaining, ())))
+    take_while1(s, take_ws).map(|(remaining, _)| (remaining, ()))
 }
 pub fn take_whitespaces0(s: String) -> Result<(String, ()), ParserError> {
-    take_while0(s, |x| take_ws(x)).and_then(|(remaining, _)| Ok((remaining, ())))
+    take_while0(s, take_ws).map(|(remaining, _)| (remaining, ()))
 }
 
 pub fn take_char(s: String, c: char) -> Result<(String, char), ParserError> {
@@ -89,10 +104,10 @@ pub fn take_char(s: String, c: char) -> Result<(String, char), ParserError> {
     first
         .filter(|x| *x == c)
         .ok_or_else(|| ParserError::newr(s.clone(), format!("Expected {} found {:#?}", c, first)))
-        .and_then(|x| Ok((chars.collect::<String>(), x)))
+        .map(|x| (chars.collect::<String>(), x))
 }
-pub fn take_str<'a>(s: String, s_to_match: &'a str) -> Result<(String, String), ParserError> {
-    let chars_to_match = s_to_match.clone().chars();
+pub fn take_str(s: String, s_to_match: &str) -> Result<(String, String), ParserError> {
+    let chars_to_match = s_to_match.chars();
     let mut schars = s.chars();
     for i in chars_to_match {
         match schars.next() {
@@ -103,7 +118,9 @@ pub fn take_str<'a>(s: String, s_to_match: &'a str) -> Result<(String, String),
                         format!(
                             "Expected {} found {:#?}",
                             s_to_match,
-                            &s[..s_to_match.len()]
+                            s.chars()
+                                .take(s_to_match.chars().count())
+                                .collect::<String>()
                         ),
                     ));
                 }
@@ -111,13 +128,25 @@ pub fn take_str<'a>(s: String, s_to_match: &'a str) -> Result<(String, String),
             None => {
                 return Err(ParserError::newr(
                     s.clone(),
-                    format!("Expected {} found {:#?}", s_to_match, &s[..0]),
+                    format!("Expected {} found {:#?}", s_to_match, s),
                 ))
             }
         }
     }
     Ok((schars.collect(), s_to_match.to_string()))
 }
+/// Like `take_str`, but the keyword can't be followed by an alphanumeric character,
+/// so that `xor` doesn't match the start of `xoring`.
+pub fn take_keyword(s: String, keyword: &str) -> Result<(String, String), ParserError> {
+    let (remaining, keyword) = take_str(s.clone(), keyword)?;
+    match take_alphanumeric(remaining.clone()) {
+        Ok(_) => Err(ParserError::newr(
+            s,
+            format!("Expected {} found a longer identifier", keyword),
+        )),
+        Err(_) => Ok((remaining, keyword)),
+    }
+}
 #[allow(dead_code)]
 pub fn take_not_char(s: String, c: char) -> Result<(String, char), ParserError> {
     let mut chars = s.chars();
@@ -125,7 +154,7 @@ pub fn take_not_char(s: String, c: char) -> Result<(String, char), ParserError>
     first
         .filter(|x| *x != c)
         .ok_or_else(|| ParserError::newr(s.clone(), format!("Expected {} found {}", c, &s[..1])))
-        .and_then(|x| Ok((chars.collect::<String>(), x)))
+        .map(|x| (chars.collect::<String>(), x))
 }
 #[allow(dead_code)]
 pub fn check_char(s: String, c: char) -> Result<(String, char), ParserError> {
@@ -140,7 +169,7 @@ pub fn check_char(s: String, c: char) -> Result<(String, char), ParserError> {
         }
     };
     if first == c {
-        return Ok((chars.collect(), first));
+        Ok((chars.collect(), first))
     } else {
         Err(ParserError::new(s))
     }
@@ -166,12 +195,12 @@ pub fn repeat0<V, T: FnMut(String) -> Result<(String, V), ParserError>>(
 pub fn repeat0_with_state<K, V, T: Fn(String, &mut K) -> Result<(String, V), ParserError>>(
     s: String,
     predicate: T,
-    mut state: &mut K,
+    state: &mut K,
 ) -> Result<(String, Vec<V>), String> {
     let mut remaining = s;
     let mut results = vec![];
     loop {
-        match predicate(remaining.clone(), &mut state) {
+        match predicate(remaining.clone(), state) {
             Ok((rem, value)) => {
                 remaining = rem;
                 results.push(value);
@@ -0,0 +1,205 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+use std::{
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// How the digits dropped when reducing the scale of a decimal are rounded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// Ties go to the even neighbour, `0.125` is `0.12` and `0.135` is `0.14`.
+    HalfEven,
+    /// Ties go away from zero, `0.125` is `0.13` and `-0.125` is `-0.13`.
+    HalfUp,
+    /// The dropped digits are ignored, `0.129` is `0.12`.
+    Truncate,
+}
+/// A base 10 number, `mantissa * 10 ^ -scale`. Additions, subtractions and
+/// multiplications are exact, rounding only happens through `Decimal::round` and
+/// `Decimal::from_rational`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+}
+impl Decimal {
+    pub fn new(mantissa: BigInt, scale: u32) -> Self {
+        Self { mantissa, scale }
+    }
+    /// Number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+    /// Rounds `num` to `scale` digits after the decimal point.
+    pub fn from_rational(num: &BigRational, scale: u32, rounding: Rounding) -> Self {
+        let numer = num.numer() * pow10(scale);
+        Self::new(round_quotient(&numer, num.denom(), rounding), scale)
+    }
+    /// Reduces the number of digits after the decimal point to at most `scale`.
+    pub fn round(&self, scale: u32, rounding: Rounding) -> Self {
+        if self.scale <= scale {
+            return self.clone();
+        }
+        let divisor = pow10(self.scale - scale);
+        Self::new(round_quotient(&self.mantissa, &divisor, rounding), scale)
+    }
+    /// Drops the zeros at the end of the decimal part, `2.50` becomes `2.5`.
+    pub fn trim(&self) -> Self {
+        let mut trimmed = self.clone();
+        let ten = BigInt::from(10);
+        while trimmed.scale > 0 && trimmed.mantissa.is_multiple_of(&ten) {
+            trimmed.mantissa /= &ten;
+            trimmed.scale -= 1;
+        }
+        trimmed
+    }
+    /// Multiplies by `10 ^ exp`, which only moves the decimal point.
+    pub fn shift(&self, exp: i32) -> Self {
+        let scale = self.scale as i64 - exp as i64;
+        if scale >= 0 {
+            Self::new(self.mantissa.clone(), scale as u32)
+        } else {
+            Self::new(&self.mantissa * pow10(-scale as u32), 0)
+        }
+    }
+    /// `None` if the scale of the power doesn't fit in a `u32`.
+    pub fn pow(&self, exp: u32) -> Option<Self> {
+        let scale = self.scale.checked_mul(exp)?;
+        Some(Self::new(self.mantissa.pow(exp), scale))
+    }
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+    /// Number of bits of the mantissa, used to bound the size of powers.
+    pub fn bits(&self) -> u64 {
+        self.mantissa.bits()
+    }
+    pub fn to_rational(&self) -> BigRational {
+        BigRational::new(self.mantissa.clone(), pow10(self.scale))
+    }
+    pub fn to_f64(&self) -> f64 {
+        // Going through the decimal representation gives the float nearest to the
+        // exact value, like a float literal would.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+    pub fn to_integer(&self) -> BigInt {
+        &self.mantissa / pow10(self.scale)
+    }
+    /// Both mantissas expressed with the biggest of the two scales.
+    fn align(self, other: Self) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (
+            self.mantissa * pow10(scale - self.scale),
+            other.mantissa * pow10(scale - other.scale),
+            scale,
+        )
+    }
+}
+impl From<BigInt> for Decimal {
+    fn from(num: BigInt) -> Self {
+        Self::new(num, 0)
+    }
+}
+impl Add for Decimal {
+    type Output = Decimal;
+    fn add(self, other: Self) -> Decimal {
+        let (op1, op2, scale) = self.align(other);
+        Decimal::new(op1 + op2, scale)
+    }
+}
+impl Sub for Decimal {
+    type Output = Decimal;
+    fn sub(self, other: Self) -> Decimal {
+        let (op1, op2, scale) = self.align(other);
+        Decimal::new(op1 - op2, scale)
+    }
+}
+impl Mul for Decimal {
+    type Output = Decimal;
+    fn mul(self, other: Self) -> Decimal {
+        Decimal::new(self.mantissa * other.mantissa, self.scale + other.scale)
+    }
+}
+impl Neg for Decimal {
+    type Output = Decimal;
+    fn neg(self) -> Decimal {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+}
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.abs().to_string();
+        let sign = if self.mantissa.is_negative() { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (int, decimals) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, int, decimals)
+    }
+}
+fn pow10(exp: u32) -> BigInt {
+    BigInt::from(10).pow(exp)
+}
+/// Divides `numer` by `denom`, rounding the result to an integer.
+fn round_quotient(numer: &BigInt, denom: &BigInt, rounding: Rounding) -> BigInt {
+    // Truncated division, the remainder has the sign of `numer`.
+    let (quotient, remainder) = numer.div_rem(denom);
+    let twice_remainder = remainder.abs() * 2;
+    let divisor = denom.abs();
+    let round_away = match rounding {
+        Rounding::Truncate => false,
+        Rounding::HalfUp => twice_remainder >= divisor,
+        Rounding::HalfEven => {
+            twice_remainder > divisor || (twice_remainder == divisor && quotient.is_odd())
+        }
+    };
+    if !round_away {
+        quotient
+    } else if numer.is_negative() != denom.is_negative() {
+        quotient - 1
+    } else {
+        quotient + 1
+    }
+}
+
+mod test {
+    use crate::decimal::{Decimal, Rounding};
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    #[test]
+    fn rounding() {
+        let dec = |mantissa: i64, scale| Decimal::new(BigInt::from(mantissa), scale);
+        let cases = vec![
+            (dec(125, 3), Rounding::HalfEven, dec(12, 2)),
+            (dec(135, 3), Rounding::HalfEven, dec(14, 2)),
+            (dec(-125, 3), Rounding::HalfEven, dec(-12, 2)),
+            (dec(125, 3), Rounding::HalfUp, dec(13, 2)),
+            (dec(-125, 3), Rounding::HalfUp, dec(-13, 2)),
+            (dec(129, 3), Rounding::Truncate, dec(12, 2)),
+            (dec(-129, 3), Rounding::Truncate, dec(-12, 2)),
+            (dec(5, 1), Rounding::HalfEven, dec(0, 0)),
+            (dec(15, 1), Rounding::HalfEven, dec(2, 0)),
+        ];
+        for (num, rounding, expected) in cases {
+            assert_eq!(num.round(expected.scale(), rounding), expected);
+        }
+        let third = BigRational::new(BigInt::from(-2), BigInt::from(3));
+        assert_eq!(
+            Decimal::from_rational(&third, 2, Rounding::HalfUp),
+            dec(-67, 2)
+        );
+        assert_eq!(
+            Decimal::from_rational(&third, 2, Rounding::Truncate),
+            dec(-66, 2)
+        );
+        assert_eq!(format!("{}", dec(-5, 3)), "-0.005");
+        assert_eq!(format!("{}", dec(250, 2).trim()), "2.5");
+        assert_eq!(format!("{}", dec(1, 0) + dec(5, 1)), "1.5");
+    }
+}
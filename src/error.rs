@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParserError {
     remaining: String,
@@ -20,3 +22,11 @@ impl ParserError {
         self.remaining.clone()
     }
 }
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "Error: {}", reason),
+            None => write!(f, "Error: couldn't parse {:#?}", self.remaining),
+        }
+    }
+}
@@ -1,32 +1,69 @@
 #![allow(unused_imports)]
+mod builtins;
 mod common;
+mod decimal;
 mod error;
 mod math;
+mod matrix;
+mod session;
+mod stats;
 
+use builtins::constant;
 use common::{
     check_char, repeat0, repeat0_with_state, take_alpha, take_alphanumeric, take_char,
-    take_identifier, take_not_char, take_str, take_while0, take_while1, take_whitespaces0,
-    take_whitespaces1,
+    take_identifier, take_keyword, take_name, take_not_char, take_str, take_while0, take_while1,
+    take_whitespaces0, take_whitespaces1, RESERVED,
 };
 use error::ParserError;
-use math::{eval_postfix, into_postfix, take_numbers, take_operator, Atom, Expr};
+use math::{
+    eval, take_numbers, take_operator, take_prefix_operator, BinaryOp, Expr, Function, Literal,
+    Number, Scope, UnaryOp,
+};
+use session::{take_base, Session};
 use std::{
     collections::HashMap,
     io::{stdin, stdout, Write},
+    rc::Rc,
 };
 
-pub fn take_atom(s: String, vec: &mut Expr) -> Result<(String, ()), ParserError> {
-    let (remaining, _) = take_whitespaces0(s)
-        .and_then(|(error, _)| take_numbers(error))
-        .and_then(|(remaining, num)| {
-            vec.push(num);
-            take_whitespaces0(remaining)
+pub fn take_atom(s: String) -> Result<(String, Expr), ParserError> {
+    let (s, _) = take_whitespaces0(s)?;
+    // Whatever starts like a number has to be one, so that the error explains what
+    // is wrong with the literal.
+    if s.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return take_numbers(s)
+            .map(|(remaining, num)| (remaining, Expr::Literal(Literal::Num(num))));
+    }
+    if let Ok((remaining, value)) =
+        take_keyword(s.clone(), "true").or_else(|_| take_keyword(s.clone(), "false"))
+    {
+        let remaining = take_whitespaces0(remaining)?.0;
+        return Ok((remaining, Expr::Literal(Literal::Bool(value == "true"))));
+    }
+    if let Ok((remaining, _)) = take_keyword(s.clone(), "if") {
+        return take_if(remaining);
+    }
+    if let Ok((remaining, _)) = take_char(s.clone(), '[') {
+        let (remaining, items) = take_args(remaining, ']')?;
+        return Ok((remaining, Expr::List(items)));
+    }
+    let (remaining, callee) = take_identifier(s.clone())
+        .and_then(|(remaining, ident)| {
+            if let Ok((remaining, _)) = take_str(remaining.clone(), "->") {
+                if !RESERVED.contains(&ident.as_str()) {
+                    return take_lambda(vec![ident], remaining);
+                }
+            }
+            Ok((remaining, Expr::Var(ident)))
         })
         .or_else(|error| {
-            take_identifier(error.remaining()).and_then(|(remaining, ident)| {
-                vec.push(Atom::Var(ident));
-                take_whitespaces0(remaining)
-            })
+            let lambda = take_char(error.remaining(), '(')
+                .and_then(|(remaining, _)| take_params(remaining))
+                .and_then(|(remaining, params)| Ok((take_str(remaining, "->")?.0, params)));
+            match lambda {
+                Ok((remaining, params)) => take_lambda(params, remaining),
+                Err(_) => Err(error),
+            }
         })
         .or_else(|error| {
             take_char(error.remaining(), '(')
@@ -36,75 +73,288 @@ pub fn take_atom(s: String, vec: &mut Expr) -> Result<(String, ()), ParserError>
                     let (remaining, _) = take_whitespaces0(remaining)
                         .and_then(|(remaining, _)| take_char(remaining, ')'))
                         .and_then(|(remaining, _)| take_whitespaces0(remaining))?;
-                    vec.push(Atom::Parens(expr));
-                    Ok((remaining, ()))
+                    Ok((remaining, expr))
                 })
         })
-        .or_else(|_| {
-            Err(ParserError::newr(
+        .map_err(|_| {
+            ParserError::newr(
                 "".to_string(),
-                format!("Expected either number or a parenthesized expression here"),
-            ))
+                "Expected either number or a parenthesized expression here".to_string(),
+            )
         })?;
-    Ok((remaining, ()))
+    take_calls(remaining, callee)
 }
-pub fn take_expr(s: String) -> Result<(String, Expr), ParserError> {
-    let mut expr = vec![];
-    let (remaining, _) = take_atom(s, &mut expr).and_then(|(remaining, _)| {
-        repeat0(remaining, |remaining| {
-            take_operator(remaining).and_then(|(remaining, op)| {
-                expr.push(op);
-                let (remaining, _) = take_whitespaces0(remaining)?;
-                take_atom(remaining, &mut expr)
-            })
-        })
-    })?;
+/// Parses the argument lists following a callee, which can themselves return a
+/// function: `adder(1)(2)`.
+fn take_calls(s: String, callee: Expr) -> Result<(String, Expr), ParserError> {
+    let (mut remaining, mut expr) = (s, callee);
+    while let Ok((rest, _)) = take_char(remaining.clone(), '(') {
+        let (rest, args) = take_args(rest, ')')?;
+        remaining = rest;
+        expr = Expr::Call(Box::new(expr), args);
+    }
     Ok((remaining, expr))
 }
-pub fn eval_expr(
-    s: String,
-    variables: &mut HashMap<String, f32>,
-) -> Result<(String, f32), ParserError> {
-    let mut rem = String::new();
-    let result = take_expr(s)
-        .and_then(|(remaining, expr)| {
-            rem = remaining;
-            into_postfix(expr)
-        })
-        .and_then(|expr| eval_postfix(expr, variables))?;
-    Ok((rem, result))
+/// Parses `if cond then a else b`, the `if` being already consumed. The `else`
+/// branch extends as far as possible, like the operand of a prefix operator.
+fn take_if(s: String) -> Result<(String, Expr), ParserError> {
+    let (remaining, cond) = take_expr(s)?;
+    let (remaining, _) = take_keyword(remaining, "then")?;
+    let (remaining, then) = take_expr(remaining)?;
+    let (remaining, _) = take_keyword(remaining, "else")?;
+    let (remaining, otherwise) = take_expr(remaining)?;
+    Ok((
+        remaining,
+        Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise)),
+    ))
+}
+/// Parses the body of a lambda, the parameters and the arrow being already
+/// consumed. Like the `else` branch of an `if`, it extends as far as possible.
+fn take_lambda(params: Vec<String>, s: String) -> Result<(String, Expr), ParserError> {
+    let (remaining, body) = take_expr(s)?;
+    Ok((remaining, Expr::Lambda(params, Box::new(body))))
 }
-pub fn take_decl(
-    s: String,
-    variables: &mut HashMap<String, f32>,
-) -> Result<(String, f32), ParserError> {
+/// Parses the comma separated arguments of a call or the items of a list, up to
+/// `close`, the opening bracket being already consumed.
+fn take_args(s: String, close: char) -> Result<(String, Vec<Expr>), ParserError> {
+    let (remaining, _) = take_whitespaces0(s)?;
+    if let Ok((remaining, _)) = take_char(remaining.clone(), close) {
+        return Ok((take_whitespaces0(remaining)?.0, vec![]));
+    }
+    let (remaining, first) = take_expr(remaining)?;
+    let (remaining, others) = repeat0(remaining, |remaining| {
+        take_char(remaining, ',').and_then(|(remaining, _)| take_expr(remaining))
+    })?;
+    let (remaining, _) = take_char(remaining, close)?;
+    let mut args = vec![first];
+    args.extend(others);
+    Ok((take_whitespaces0(remaining)?.0, args))
+}
+pub fn take_unary(s: String) -> Result<(String, Expr), ParserError> {
+    match take_whitespaces0(s).and_then(|(remaining, _)| take_prefix_operator(remaining)) {
+        Ok((remaining, op)) => {
+            let (remaining, operand) = take_binary(remaining, op.precedence())?;
+            Ok((remaining, Expr::Unary(op, Box::new(operand))))
+        }
+        Err(error) => take_atom(error.remaining()),
+    }
+}
+/// Precedence climbing: parses a chain of binary operators whose precedence is
+/// at least `min_precedence`, leaving the looser ones to the caller.
+pub fn take_binary(s: String, min_precedence: i32) -> Result<(String, Expr), ParserError> {
+    let (mut remaining, mut lhs) = take_unary(s)?;
+    while let Ok((rem, op)) = take_operator(remaining.clone()) {
+        if op.precedence() < min_precedence {
+            break;
+        }
+        let next_precedence = if op.is_right_assoc() {
+            op.precedence()
+        } else {
+            op.precedence() + 1
+        };
+        let (rem, rhs) = take_binary(rem, next_precedence)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        remaining = rem;
+    }
+    Ok((remaining, lhs))
+}
+pub fn take_expr(s: String) -> Result<(String, Expr), ParserError> {
+    take_binary(s, 0)
+}
+pub fn eval_expr(s: String, session: &mut Session) -> Result<(String, Literal), ParserError> {
+    let (remaining, expr) = take_expr(s)?;
+    Ok((remaining, eval(&expr, &Scope::new(session))?))
+}
+/// Parses `let name = expr;`. Constants can only be redefined with the explicit
+/// `let shadow pi = 3;`.
+pub fn take_decl(s: String, session: &mut Session) -> Result<(String, Literal), ParserError> {
     let mut ident = String::new();
-    let (remaining, value) = take_str(s.clone(), "let")
+    let mut shadow = false;
+    let (remaining, _) = take_str(s.clone(), "let")
         .and_then(|(remaining, _)| take_whitespaces1(remaining))
         .and_then(|(remaining, _)| take_identifier(remaining))
         .and_then(|(remaining, identifier)| {
-            ident = identifier;
-            take_whitespaces0(remaining)
+            // `let shadow = 1;` declares a variable named `shadow`.
+            match take_identifier(remaining.clone()) {
+                Ok((remaining, name)) if identifier == "shadow" => {
+                    shadow = true;
+                    ident = name;
+                    take_whitespaces0(remaining)
+                }
+                _ => {
+                    ident = identifier;
+                    take_whitespaces0(remaining)
+                }
+            }
         })
         .and_then(|(remaining, _)| take_char(remaining, '='))
         .and_then(|(remaining, _)| take_whitespaces0(remaining))
-        .and_then(|(remaining, _)| eval_expr(remaining, variables))
-        .and_then(|(remaining, value)| Ok((take_char(remaining, ';')?.0, value)))
-        .or_else(|_| Err(ParserError::new(s)))?;
-    variables.insert(ident, value);
+        .map_err(|_| ParserError::new(s.clone()))?;
+    // Errors of the expression, such as a division by zero, are reported as is.
+    let (remaining, value) = eval_expr(remaining, session)?;
+    let (remaining, _) = take_char(remaining, ';').map_err(|_| ParserError::new(s.clone()))?;
+    take_end(remaining.clone())?;
+    if RESERVED.contains(&ident.as_str()) {
+        return Err(ParserError::newr(
+            s,
+            format!("{:#?} is a reserved word, it can't be a variable", ident),
+        ));
+    }
+    if constant(&ident).is_some() && !shadow {
+        return Err(ParserError::newr(
+            s,
+            format!(
+                "{:#?} is a constant, use `let shadow {} = ...;` to redefine it",
+                ident, ident
+            ),
+        ));
+    }
+    // A lambda is named after the variable it is first bound to, which lets it
+    // call itself through that name.
+    let value = match value {
+        Literal::Function(function) if function.name.is_none() => {
+            let mut function = (*function).clone();
+            function.name = Some(ident.clone());
+            Literal::Function(Rc::new(function))
+        }
+        value => value,
+    };
+    session.variables.insert(ident, value.clone());
     Ok((remaining, value))
 }
-pub fn eval_line(
-    s: String,
-    variables: &mut HashMap<String, f32>,
-) -> Result<(String, f32), ParserError> {
-    let result = take_decl(s, variables).or_else(|error| eval_expr(error.remaining(), variables));
-    println!("{:#?}", variables);
-    println!("{:#?}", result);
-    result
+/// Nothing can follow a declaration, which is checked before anything is
+/// declared.
+fn take_end(s: String) -> Result<(String, ()), ParserError> {
+    if s.trim().is_empty() {
+        Ok((s, ()))
+    } else {
+        Err(ParserError::newr(
+            s.clone(),
+            format!("Unexpected input after the declaration: {:#?}", s.trim()),
+        ))
+    }
 }
+/// Parses `fn name(params) = expr;` and stores the function with the variables.
+pub fn take_fn_decl(s: String, session: &mut Session) -> Result<(String, Literal), ParserError> {
+    let (remaining, name) = take_keyword(s.clone(), "fn")
+        .and_then(|(remaining, _)| take_whitespaces1(remaining))
+        .and_then(|(remaining, _)| take_identifier(remaining))
+        .map_err(|_| ParserError::new(s.clone()))?;
+    let (remaining, params) = take_char(remaining, '(')
+        .and_then(|(remaining, _)| take_params(remaining))
+        .and_then(|(remaining, params)| Ok((take_char(remaining, '=')?.0, params)))
+        .map_err(|_| ParserError::new(s.clone()))?;
+    let (remaining, body) = take_expr(remaining)?;
+    let (remaining, _) = take_char(remaining, ';')?;
+    take_end(remaining.clone())?;
+    if RESERVED.contains(&name.as_str()) {
+        return Err(ParserError::newr(
+            s,
+            format!("{:#?} is a reserved word, it can't be a function", name),
+        ));
+    }
+    if constant(&name).is_some() {
+        return Err(ParserError::newr(
+            s,
+            format!("{:#?} is a constant, it can't be a function", name),
+        ));
+    }
+    let function = Literal::Function(Rc::new(Function {
+        name: Some(name.clone()),
+        params,
+        body,
+        captured: HashMap::new(),
+    }));
+    session.variables.insert(name, function.clone());
+    Ok((remaining, function))
+}
+/// Parses the comma separated parameters of a function, the opening parenthesis
+/// being already consumed.
+fn take_params(s: String) -> Result<(String, Vec<String>), ParserError> {
+    let (remaining, _) = take_whitespaces0(s)?;
+    if let Ok((remaining, _)) = take_char(remaining.clone(), ')') {
+        return Ok((take_whitespaces0(remaining)?.0, vec![]));
+    }
+    let (remaining, first) = take_name(remaining)?;
+    let (remaining, others) = repeat0(remaining, |remaining| {
+        take_char(remaining, ',')
+            .and_then(|(remaining, _)| take_whitespaces0(remaining))
+            .and_then(|(remaining, _)| take_name(remaining))
+    })?;
+    let (remaining, _) = take_char(remaining, ')')?;
+    let mut params = vec![first];
+    params.extend(others);
+    Ok((take_whitespaces0(remaining)?.0, params))
+}
+pub fn eval_line(s: String, session: &mut Session) -> Result<(String, Literal), ParserError> {
+    if take_keyword(s.clone(), "let").is_ok() {
+        take_decl(s, session)
+    } else if take_keyword(s.clone(), "fn").is_ok() {
+        take_fn_decl(s, session)
+    } else {
+        eval_expr(s, session)
+    }
+}
+/// Evaluates a line and formats its result as the REPL displays it. The line can
+/// end with `as <base>` to display an integer in another base, as in `255 as hex`.
+pub fn run_line(s: String, session: &mut Session) -> Result<String, ParserError> {
+    let (remaining, result) = eval_line(s, session)?;
+    let settings = &session.settings;
+    let (remaining, base) = match take_keyword(remaining.clone(), "as") {
+        Ok((remaining, _)) => {
+            let remaining = take_whitespaces0(remaining)?.0;
+            let remaining = take_keyword(remaining.clone(), "base")
+                .map(|(remaining, _)| remaining)
+                .unwrap_or(remaining);
+            let base = take_base(remaining)?;
+            ("".to_string(), Some(base))
+        }
+        Err(_) => (remaining, None),
+    };
+    if !remaining.trim().is_empty() {
+        return Err(ParserError::newr(
+            remaining.clone(),
+            format!("Unexpected input after the result: {:#?}", remaining.trim()),
+        ));
+    }
+    match base {
+        Some(base) => {
+            let radix = match &result {
+                Literal::Num(num) => num.to_radix(base),
+                _ => None,
+            };
+            radix.ok_or_else(|| {
+                ParserError::newr(
+                    "".to_string(),
+                    format!(
+                        "Only integers can be displayed in base {}, not {}",
+                        base, result
+                    ),
+                )
+            })
+        }
+        None => {
+            let radix = match &result {
+                Literal::Num(num) => num.to_radix(settings.base),
+                _ => None,
+            };
+            Ok(radix.unwrap_or_else(|| format!("{:.*}", settings.precision, result)))
+        }
+    }
+}
+/// Stack of the thread running the REPL, big enough for the deepest recursion
+/// allowed by `:maxdepth`.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 fn main() {
-    let mut variables = HashMap::new();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(repl)
+        .expect("Failed to start the REPL")
+        .join()
+        .expect("The REPL panicked");
+}
+fn repl() {
+    let mut session = Session::new();
     loop {
         let mut input = String::new();
         print!(">>> ");
@@ -114,28 +364,457 @@ fn main() {
         if input == "quit" {
             break;
         }
-        eval_line(input.to_string(), &mut variables);
+        if input.starts_with(':') {
+            if let Err(error) = session.run_command(input.to_string()) {
+                println!("{}", error);
+            }
+            continue;
+        }
+        match run_line(input.to_string(), &mut session) {
+            Ok(result) => println!("{}", result),
+            Err(error) => println!("{}", error),
+        }
         //println!("{:#?}", take_numbers(input.to_string()));
     }
 }
 
 mod test {
-    use crate::eval_line;
+    use crate::error::ParserError;
+    use crate::math::{BinaryOp, Expr, Literal, Number, UnaryOp};
+    use crate::session::Session;
+    use crate::{eval_line, run_line, take_expr};
     use std::collections::HashMap;
 
+    #[test]
+    fn ast() {
+        fn int(num: i32) -> Box<Expr> {
+            Box::new(Expr::Literal(Literal::Num(Number::I32(num))))
+        }
+        assert_eq!(
+            take_expr("2 ^ 3 ^ 2".to_string()),
+            Ok((
+                "".to_string(),
+                Expr::Binary(
+                    BinaryOp::Pow,
+                    int(2),
+                    Box::new(Expr::Binary(BinaryOp::Pow, int(3), int(2)))
+                )
+            ))
+        );
+        assert_eq!(
+            take_expr("1 - 2 - 3".to_string()),
+            Ok((
+                "".to_string(),
+                Expr::Binary(
+                    BinaryOp::Sub,
+                    Box::new(Expr::Binary(BinaryOp::Sub, int(1), int(2))),
+                    int(3)
+                )
+            ))
+        );
+        assert_eq!(
+            take_expr("-2 ^ 2 * x".to_string()),
+            Ok((
+                "".to_string(),
+                Expr::Binary(
+                    BinaryOp::Mul,
+                    Box::new(Expr::Unary(
+                        UnaryOp::Neg,
+                        Box::new(Expr::Binary(BinaryOp::Pow, int(2), int(2)))
+                    )),
+                    Box::new(Expr::Var("x".to_string()))
+                )
+            ))
+        );
+        assert_eq!(
+            take_expr("f(1, (2), g())".to_string()),
+            Ok((
+                "".to_string(),
+                Expr::Call(
+                    Box::new(Expr::Var("f".to_string())),
+                    vec![
+                        *int(1),
+                        *int(2),
+                        Expr::Call(Box::new(Expr::Var("g".to_string())), vec![])
+                    ]
+                )
+            ))
+        );
+        assert_eq!(
+            take_expr("f(1)(2)".to_string()),
+            Ok((
+                "".to_string(),
+                Expr::Call(
+                    Box::new(Expr::Call(
+                        Box::new(Expr::Var("f".to_string())),
+                        vec![*int(1)]
+                    )),
+                    vec![*int(2)]
+                )
+            ))
+        );
+    }
+
     #[test]
     fn var_decl() {
-        let mut variables = HashMap::new();
+        let mut session = Session::new();
         assert_eq!(
-            eval_line("let a = 15 * 2;".to_string(), &mut variables),
-            Ok(("".to_string(), 30))
+            eval_line("let a = 15 * 2;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(30))))
         );
         let mut to_compare = HashMap::new();
-        to_compare.insert("a".to_string(), 30);
-        assert_eq!(variables, to_compare);
+        to_compare.insert("a".to_string(), Literal::Num(Number::I32(30)));
+        assert_eq!(session.variables, to_compare);
+        assert_eq!(
+            eval_line("(a + 2) * 3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32((30 + 2) * 3))))
+        );
+        assert_eq!(
+            eval_line("let b = 1.5;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(1.5))))
+        );
+        assert_eq!(
+            eval_line("let ok = b > 1;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Bool(true)))
+        );
+        assert_eq!(
+            eval_line("b * 2.0".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(3.))))
+        );
+        // Runtime errors in the value are reported as is, and declare nothing.
+        assert_eq!(
+            eval_line("let c = 1/0;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Division by zero".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("let c = undefined + 1;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Undefined variable: \"undefined\"".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("let c = atan2(1);".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "atan2 expects 2 argument(s), found 1".to_string()
+            ))
+        );
+        assert!(!session.variables.contains_key("c"));
+        assert_eq!(
+            eval_line("let c = 1".to_string(), &mut session),
+            Err(ParserError::new("let c = 1".to_string()))
+        );
+        assert_eq!(
+            eval_line("let = 1;".to_string(), &mut session),
+            Err(ParserError::new("let = 1;".to_string()))
+        );
+    }
+
+    #[test]
+    fn radix_output() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("255 as hex", "0xff"),
+            ("255 as bin", "0b11111111"),
+            ("8 as oct", "0o10"),
+            ("35 as 36", "36#z"),
+            ("71 as base 36", "36#1z"),
+            ("-1 as hex", "0xffffffff"),
+            ("-2147483649 as hex", "0xffffffff7fffffff"),
+            ("-(2^64) as hex", "-0x10000000000000000"),
+            ("0x1F as dec", "31"),
+            ("1.5", "1.5"),
+        ];
+        for (line, result) in cases {
+            assert_eq!(
+                run_line(line.to_string(), &mut session),
+                Ok(result.to_string()),
+                "{}",
+                line
+            );
+        }
+        assert!(run_line("1.5 as hex".to_string(), &mut session).is_err());
+        assert!(run_line("1 as 37".to_string(), &mut session).is_err());
+        // Nothing can follow the expression, or its display modifier.
+        assert_eq!(
+            run_line("1 + 2 foo".to_string(), &mut session),
+            Err(ParserError::newr(
+                "foo".to_string(),
+                "Unexpected input after the result: \"foo\"".to_string()
+            ))
+        );
+        assert!(run_line("255 ashex".to_string(), &mut session).is_err());
+        assert!(run_line("255 as hexx".to_string(), &mut session).is_err());
+        assert!(run_line("fn g(x) = x; 5".to_string(), &mut session).is_err());
+        assert_eq!(
+            run_line("let z = 1; 2".to_string(), &mut session),
+            Err(ParserError::newr(
+                " 2".to_string(),
+                "Unexpected input after the declaration: \"2\"".to_string()
+            ))
+        );
+        assert!(!session.variables.contains_key("g"));
+        assert!(!session.variables.contains_key("z"));
+        assert_eq!(
+            run_line("let y = 1;  ".to_string(), &mut session),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            run_line("255 as base 16".to_string(), &mut session),
+            Ok("0xff".to_string())
+        );
+        session.settings.base = 16;
+        assert_eq!(
+            run_line("10 + 5".to_string(), &mut session),
+            Ok("0xf".to_string())
+        );
+        assert_eq!(
+            run_line("1 / 4".to_string(), &mut session),
+            Ok("0.25".to_string())
+        );
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut session = Session::new();
+        let (_, hyp) =
+            eval_line("fn hyp(a, b) = sqrt(a*a + b*b);".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", hyp), "fn hyp(a, b)");
+        assert_eq!(
+            eval_line("hyp(3, 4) * 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(10.))))
+        );
+        assert_eq!(
+            eval_line("hyp(3)".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "hyp expects 2 argument(s), found 1".to_string()
+            ))
+        );
+        // Parameters shadow the variables of the session, and don't leak out.
+        eval_line("let a = 100;".to_string(), &mut session).unwrap();
+        eval_line("fn inc(a) = a + 1;".to_string(), &mut session).unwrap();
+        eval_line("fn plusa(x) = x + a;".to_string(), &mut session).unwrap();
+        assert_eq!(
+            eval_line("inc(1) + plusa(1)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(103))))
+        );
+        assert_eq!(
+            session.variables.get("a"),
+            Some(&Literal::Num(Number::I32(100)))
+        );
+        eval_line("fn caller(y) = callee();".to_string(), &mut session).unwrap();
+        eval_line("fn callee() = y;".to_string(), &mut session).unwrap();
+        assert!(eval_line("caller(1)".to_string(), &mut session).is_err());
+        assert!(eval_line("y".to_string(), &mut session).is_err());
+        // Recursion, with a bounded depth.
+        eval_line(
+            "fn fact(n) = if n <= 1 then 1 else n * fact(n - 1);".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(
+            eval_line("fact(20)".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::I64(2432902008176640000))
+            ))
+        );
+        eval_line("fn forever(n) = forever(n + 1);".to_string(), &mut session).unwrap();
+        assert_eq!(
+            eval_line("forever(0)".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Maximum recursion depth of 200 exceeded while calling forever".to_string()
+            ))
+        );
+        session.run_command(":maxdepth 10".to_string()).unwrap();
+        assert!(eval_line("fact(11)".to_string(), &mut session).is_err());
+        assert!(eval_line("fact(10)".to_string(), &mut session).is_ok());
+        assert!(eval_line("fn pi() = 3;".to_string(), &mut session).is_err());
+        assert!(eval_line("fn f(x = x;".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn reserved_words() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_line("let true = 1;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "let true = 1;".to_string(),
+                "\"true\" is a reserved word, it can't be a variable".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("fn if(x) = x;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "fn if(x) = x;".to_string(),
+                "\"if\" is a reserved word, it can't be a function".to_string()
+            ))
+        );
+        for line in [
+            "let and = 3;",
+            "let not = 1;",
+            "let then = 1;",
+            "let shadow xor = 1;",
+            "let false = 0;",
+            "fn else() = 1;",
+            "fn f(or) = 1;",
+            "fn f(x, then) = x;",
+            "let f = (a, else) -> a;",
+            "then -> 1",
+            "let let = 1;",
+            "let fn = 3;",
+            "let as = 1;",
+            "fn fn(x) = x;",
+            "fn f(let) = 1;",
+            "as -> 1",
+        ] {
+            assert!(
+                eval_line(line.to_string(), &mut session).is_err(),
+                "{}",
+                line
+            );
+        }
+        assert!(session.variables.is_empty());
+        assert_eq!(
+            eval_line("let as = 1;".to_string(), &mut session),
+            Err(ParserError::newr(
+                "let as = 1;".to_string(),
+                "\"as\" is a reserved word, it can't be a variable".to_string()
+            ))
+        );
+        // `shadow` is only a keyword right after `let`.
+        assert_eq!(
+            eval_line("let shadow shadow = 2;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(2))))
+        );
+        // Words that merely start like a reserved one are fine.
+        assert_eq!(
+            eval_line("let android = 3;".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_line(
+                "let f = (iffy, thenx) -> iffy + thenx;".to_string(),
+                &mut session
+            )
+            .map(|(_, f)| format!("{}", f)),
+            Ok("fn f(iffy, thenx)".to_string())
+        );
+    }
+    #[test]
+    fn lambdas() {
+        let mut session = Session::new();
+        let (_, square) = eval_line("let sq = x -> x^2;".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", square), "fn sq(x)");
+        assert_eq!(
+            eval_line("sq(3) + 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(10))))
+        );
+        assert_eq!(
+            eval_line("let mul = (a, b) -> a * b;".to_string(), &mut session)
+                .map(|(_, f)| format!("{}", f)),
+            Ok("fn mul(a, b)".to_string())
+        );
+        assert_eq!(
+            eval_line("mul(3, 4)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(12))))
+        );
+        assert_eq!(
+            eval_line("mul(3)".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "mul expects 2 argument(s), found 1".to_string()
+            ))
+        );
+        // Captured variables keep the value they had when the lambda was created.
+        eval_line("let k = 2;".to_string(), &mut session).unwrap();
+        eval_line("let scale = x -> x * k;".to_string(), &mut session).unwrap();
+        eval_line("let k = 10;".to_string(), &mut session).unwrap();
+        assert_eq!(
+            eval_line("scale(3)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(6))))
+        );
+        // Including the parameters of the function that returned the lambda.
+        eval_line("let adder = n -> x -> x + n;".to_string(), &mut session).unwrap();
+        eval_line("let inc = adder(1);".to_string(), &mut session).unwrap();
+        assert_eq!(
+            eval_line("inc(5)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(6))))
+        );
+        // A lambda can call itself through the variable it is bound to.
+        eval_line(
+            "let fact = n -> if n <= 1 then 1 else n * fact(n - 1);".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(
+            eval_line("fact(5)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(120))))
+        );
+        // Even when the name was already bound.
+        eval_line("let h = x -> x + 1;".to_string(), &mut session).unwrap();
+        eval_line(
+            "let h = x -> if x == 0 then 0 else h(x - 1) + 10;".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(
+            eval_line("h(2)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(20))))
+        );
+        eval_line("let fact = 1;".to_string(), &mut session).unwrap();
+        eval_line(
+            "let fact = n -> if n <= 1 then 1 else n * fact(n - 1);".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(
+            eval_line("fact(4)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(24))))
+        );
+        // The name is bound when the lambda is called, so a copy still works after
+        // the original variable is redefined.
+        eval_line(
+            "let f = n -> if n == 0 then 0 else f(n - 1) + 1;".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        eval_line("let g = f;".to_string(), &mut session).unwrap();
+        eval_line("let f = 1;".to_string(), &mut session).unwrap();
+        assert_eq!(
+            eval_line("g(3)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_line("g".to_string(), &mut session).map(|(_, g)| format!("{}", g)),
+            Ok("fn f(n)".to_string())
+        );
+        // Any expression giving a function can be called.
+        assert_eq!(
+            eval_line("adder(1)(2)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_line("(x -> x * 2)(4)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(8))))
+        );
+        assert_eq!(
+            eval_line("(1 + 2)(3)".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Call expects a function, found 3".to_string()
+            ))
+        );
         assert_eq!(
-            eval_line("(a + 2) * 3".to_string(), &mut variables),
-            Ok(("".to_string(), (30 + 2) * 3))
+            eval_line("(1 + 2) * 3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(9))))
         );
+        assert!(eval_line("k(1)".to_string(), &mut session).is_err());
+        assert!(eval_line("(a, 1) -> a".to_string(), &mut session).is_err());
     }
 }
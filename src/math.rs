@@ -1,407 +1,2042 @@
 use crate::{
-    common::{take_char, take_str, take_while0, take_while1, take_whitespaces0},
+    builtins::{call_builtin, constant, series},
+    common::{
+        take_alphanumeric, take_char, take_keyword, take_str, take_while0, take_whitespaces0,
+    },
+    decimal::{Decimal, Rounding},
     error::ParserError,
+    matrix::eval_list_op,
+    session::{Session, Settings},
 };
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
 use std::{
+    cmp::Ordering,
     collections::HashMap,
+    convert::TryFrom,
+    fmt,
     num::ParseIntError,
-    ops::{Add, Div, Mul, Sub},
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Shl, Shr, Sub},
+    rc::Rc,
 };
 
-pub type Expr = Vec<Atom>;
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Var(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `f(a, b)`, the callee being any expression that gives a function.
+    Call(Box<Expr>, Vec<Expr>),
+    /// `if cond then a else b`, only the branch taken is evaluated.
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `(a, b) -> a + b`, or `x -> x ^ 2` with a single parameter.
+    Lambda(Vec<String>, Box<Expr>),
+    List(Vec<Expr>),
+}
+impl Expr {
+    /// Collects the variables the expression reads without binding them, which is
+    /// what a lambda captures.
+    fn free_vars(&self, bound: &[String], vars: &mut Vec<String>) {
+        let mut add = |name: &String| {
+            if !bound.contains(name) && !vars.contains(name) {
+                vars.push(name.clone())
+            }
+        };
+        match self {
+            Expr::Literal(_) => (),
+            Expr::Var(name) => add(name),
+            Expr::Call(callee, args) => {
+                callee.free_vars(bound, vars);
+                args.iter().for_each(|arg| arg.free_vars(bound, vars));
+            }
+            Expr::Unary(_, operand) => operand.free_vars(bound, vars),
+            Expr::Binary(_, operand1, operand2) => {
+                operand1.free_vars(bound, vars);
+                operand2.free_vars(bound, vars);
+            }
+            Expr::If(cond, then, otherwise) => {
+                cond.free_vars(bound, vars);
+                then.free_vars(bound, vars);
+                otherwise.free_vars(bound, vars);
+            }
+            Expr::Lambda(params, body) => {
+                let bound = [bound, params].concat();
+                body.free_vars(&bound, vars);
+            }
+            Expr::List(items) => items.iter().for_each(|item| item.free_vars(bound, vars)),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Number {
     U32(u32),
     I32(i32),
-    F32(f32),
+    U64(u64),
+    I64(i64),
+    BigInt(BigInt),
+    Decimal(Decimal),
+    Rational(BigRational),
+    F64(f64),
+    Complex(Complex64),
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     U32,
     I32,
-    F32,
+    U64,
+    I64,
+    BigInt,
+    Decimal,
+    Rational,
+    F64,
+    Complex,
+    Bool,
 }
+/// Mixed operands are promoted before applying an operator, following the order
+/// `Complex`, `F64`, `Rational`, `Decimal`: as soon as one side has one of these
+/// types both are computed with it. Integers are computed exactly, then
+/// stored in the narrowest type at least as wide as both operands that can hold
+/// the result, see `Number::from_bigint`.
 impl Add for Number {
-    type Output = f32;
-    fn add(self, other: Self) -> f32 {
-        match self {
-            Number::F32(op1) => match other {
-                Number::F32(op2) => op1 + op2,
-                _ => unreachable!(),
-            },
-            Number::I32(op1) => match other {
-                Number::I32(op2) => (op1 + op2) as f32,
-                Number::U32(op2) => (op1 as u32 + op2) as f32,
-                _ => unreachable!(),
-            },
-            Number::U32(op1) => match other {
-                Number::I32(op2) => (op1 as i32 + op2) as f32,
-                Number::U32(op2) => (op1 + op2 as u32) as f32,
-                _ => unreachable!(),
-            },
+    type Output = Result<Number, ParserError>;
+    fn add(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            return Ok(Number::Complex(self.as_complex() + other.as_complex()));
+        }
+        if self.is_float() || other.is_float() {
+            return Ok(Number::F64(self.as_f64() + other.as_f64()));
+        }
+        if self.is_rational() || other.is_rational() {
+            return Ok(Number::from_rational(
+                self.as_rational() + other.as_rational(),
+            ));
         }
+        if self.is_decimal() || other.is_decimal() {
+            return Ok(Number::Decimal(self.as_decimal() + other.as_decimal()));
+        }
+        self.int_op(other, "+", |op1, op2| Some(op1 + op2))
     }
 }
 impl Sub for Number {
-    type Output = f32;
-    fn sub(self, other: Self) -> f32 {
-        match self {
-            Number::F32(op1) => match other {
-                Number::F32(op2) => op1 - op2,
-                _ => unreachable!(),
-            },
-            Number::I32(op1) => match other {
-                Number::I32(op2) => (op1 - op2) as f32,
-                Number::U32(op2) => (op1 as u32 - op2) as f32,
-                _ => unreachable!(),
-            },
-            Number::U32(op1) => match other {
-                Number::I32(op2) => (op1 as i32 - op2) as f32,
-                Number::U32(op2) => (op1 - op2 as u32) as f32,
-                _ => unreachable!(),
-            },
+    type Output = Result<Number, ParserError>;
+    fn sub(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            return Ok(Number::Complex(self.as_complex() - other.as_complex()));
+        }
+        if self.is_float() || other.is_float() {
+            return Ok(Number::F64(self.as_f64() - other.as_f64()));
+        }
+        if self.is_rational() || other.is_rational() {
+            return Ok(Number::from_rational(
+                self.as_rational() - other.as_rational(),
+            ));
         }
+        if self.is_decimal() || other.is_decimal() {
+            return Ok(Number::Decimal(self.as_decimal() - other.as_decimal()));
+        }
+        self.int_op(other, "-", |op1, op2| Some(op1 - op2))
     }
 }
 impl Mul for Number {
-    type Output = f32;
-    fn mul(self, other: Self) -> f32 {
-        match self {
-            Number::F32(op1) => match other {
-                Number::F32(op2) => op1 * op2,
-                _ => unreachable!(),
-            },
-            Number::I32(op1) => match other {
-                Number::I32(op2) => (op1 * op2) as f32,
-                Number::U32(op2) => (op1 as u32 * op2) as f32,
-                _ => unreachable!(),
-            },
-            Number::U32(op1) => match other {
-                Number::I32(op2) => (op1 as i32 * op2) as f32,
-                Number::U32(op2) => (op1 * op2 as u32) as f32,
-                _ => unreachable!(),
-            },
+    type Output = Result<Number, ParserError>;
+    fn mul(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            return Ok(Number::Complex(self.as_complex() * other.as_complex()));
+        }
+        if self.is_float() || other.is_float() {
+            return Ok(Number::F64(self.as_f64() * other.as_f64()));
+        }
+        if self.is_rational() || other.is_rational() {
+            return Ok(Number::from_rational(
+                self.as_rational() * other.as_rational(),
+            ));
         }
+        if self.is_decimal() || other.is_decimal() {
+            return Ok(Number::Decimal(self.as_decimal() * other.as_decimal()));
+        }
+        self.int_op(other, "*", |op1, op2| Some(op1 * op2))
     }
 }
+/// `/` always gives a float, `//` is there to stay in integer arithmetic.
 impl Div for Number {
-    type Output = f32;
-    fn div(self, other: Self) -> f32 {
+    type Output = Result<Number, ParserError>;
+    fn div(self, other: Self) -> Result<Number, ParserError> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        if self.is_complex() || other.is_complex() {
+            return Ok(Number::Complex(self.as_complex() / other.as_complex()));
+        }
+        Ok(Number::F64(self.as_f64() / other.as_f64()))
+    }
+}
+impl Neg for Number {
+    type Output = Result<Number, ParserError>;
+    /// Negating an unsigned integer gives a signed one.
+    fn neg(self) -> Result<Number, ParserError> {
         match self {
-            Number::F32(op1) => match other {
-                Number::F32(op2) => op1 * op2,
-                _ => unreachable!(),
-            },
-            Number::I32(op1) => match other {
-                Number::I32(op2) => (op1 * op2) as f32,
-                Number::U32(op2) => (op1 as u32 * op2) as f32,
-                _ => unreachable!(),
-            },
-            Number::U32(op1) => match other {
-                Number::I32(op2) => (op1 as i32 * op2) as f32,
-                Number::U32(op2) => (op1 * op2 as u32) as f32,
-                _ => unreachable!(),
-            },
+            Number::F64(num) => Ok(Number::F64(-num)),
+            Number::Rational(num) => Ok(Number::Rational(-num)),
+            Number::Decimal(num) => Ok(Number::Decimal(-num)),
+            Number::Complex(num) => Ok(Number::Complex(-num)),
+            _ => Ok(Number::from_bigint(-self.as_bigint(), self.width(), false)),
+        }
+    }
+}
+/// Bitwise operators only apply to integers, which are seen as infinite two's
+/// complement sequences of bits: `-1 & 0xff` is `255`.
+impl BitAnd for Number {
+    type Output = Result<Number, ParserError>;
+    fn bitand(self, other: Self) -> Result<Number, ParserError> {
+        self.bitwise_op(other, "&", |op1, op2| Some(op1 & op2))
+    }
+}
+impl BitOr for Number {
+    type Output = Result<Number, ParserError>;
+    fn bitor(self, other: Self) -> Result<Number, ParserError> {
+        self.bitwise_op(other, "|", |op1, op2| Some(op1 | op2))
+    }
+}
+impl BitXor for Number {
+    type Output = Result<Number, ParserError>;
+    fn bitxor(self, other: Self) -> Result<Number, ParserError> {
+        self.bitwise_op(other, "xor", |op1, op2| Some(op1 ^ op2))
+    }
+}
+impl Shl for Number {
+    type Output = Result<Number, ParserError>;
+    fn shl(self, other: Self) -> Result<Number, ParserError> {
+        self.bitwise_op(other, "<<", |num, shift| {
+            let shift = shift.to_u64()?;
+            if num.bits() + shift > MAX_POW_BITS {
+                return None;
+            }
+            Some(num << shift as usize)
+        })
+    }
+}
+/// `>>` is an arithmetic shift, it rounds towards negative infinity like `//`.
+impl Shr for Number {
+    type Output = Result<Number, ParserError>;
+    fn shr(self, other: Self) -> Result<Number, ParserError> {
+        self.bitwise_op(other, ">>", |num, shift| {
+            // Past the last bit only the sign is left.
+            let shift = shift.to_u64()?.min(num.bits() + 1);
+            Some(num >> shift as usize)
+        })
+    }
+}
+/// `~` flips all the bits: unsigned integers keep their width, `~0` as a `U32` is
+/// `4294967295`, while for signed ones `~x` is `-x - 1`.
+impl Not for Number {
+    type Output = Result<Number, ParserError>;
+    fn not(self) -> Result<Number, ParserError> {
+        match self {
+            Number::U32(num) => Ok(Number::U32(!num)),
+            Number::U64(num) => Ok(Number::U64(!num)),
+            num if num.is_integer() => {
+                Ok(Number::from_bigint(!num.as_bigint(), num.width(), false))
+            }
+            num => Err(not_integer("~", &num)),
         }
     }
 }
+/// Biggest number of bits an integer power is allowed to produce, so that a typo
+/// such as `10 ^ 10 ^ 10` fails instead of eating all the memory.
+const MAX_POW_BITS: u64 = 1 << 20;
+impl Number {
+    /// Raises `self` to the power `other`. Integer bases stay integers as long as
+    /// the exponent is a non-negative integer, anything else is computed as a float.
+    pub fn pow(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            // `powc` goes through the logarithm of the base, which is infinite for
+            // zero, so zero bases are handled here.
+            if self.is_zero() {
+                let exp = other.as_complex();
+                if exp.is_zero() {
+                    return Ok(Number::Complex(Complex64::new(1., 0.)));
+                }
+                if exp.re > 0.0 {
+                    return Ok(Number::Complex(Complex64::zero()));
+                }
+                if exp.re < 0.0 {
+                    return Err(division_by_zero());
+                }
+            }
+            return Ok(Number::Complex(self.as_complex().powc(other.as_complex())));
+        }
+        if self.is_float() || other.is_float() || other.is_rational() || other.is_decimal() {
+            return Ok(Number::F64(self.as_f64().powf(other.as_f64())));
+        }
+        if let Number::Rational(base) = &self {
+            if base.is_zero() && other.as_bigint().is_negative() {
+                return Err(division_by_zero());
+            }
+            let bits = base.numer().bits().max(base.denom().bits());
+            return other
+                .as_bigint()
+                .to_i32()
+                .filter(|exp| bits * exp.unsigned_abs() as u64 <= MAX_POW_BITS)
+                .map(|exp| Number::from_rational(base.pow(exp)))
+                .ok_or_else(|| overflow(&self, "^", &other));
+        }
+        if other.as_bigint().is_negative() {
+            if self.is_zero() {
+                return Err(division_by_zero());
+            }
+            return Ok(Number::F64(self.as_f64().powf(other.as_f64())));
+        }
+        if let Number::Decimal(base) = &self {
+            return other
+                .as_bigint()
+                .to_u32()
+                .filter(|exp| {
+                    let exp = *exp as u64;
+                    base.bits() * exp <= MAX_POW_BITS && base.scale() as u64 * exp <= MAX_POW_BITS
+                })
+                .and_then(|exp| base.pow(exp))
+                .map(Number::Decimal)
+                .ok_or_else(|| overflow(&self, "^", &other));
+        }
+        self.int_op(other, "^", |base, exp| {
+            // The powers of 0, 1 and -1 don't grow, whatever the exponent.
+            if exp.is_zero() {
+                return Some(BigInt::from(1));
+            }
+            if base.is_zero() || base == BigInt::from(1) {
+                return Some(base);
+            }
+            if base == BigInt::from(-1) {
+                return Some(if exp.is_odd() { base } else { -base });
+            }
+            let exp = exp.to_u32()?;
+            if base.bits() * exp as u64 > MAX_POW_BITS {
+                return None;
+            }
+            Some(base.pow(exp))
+        })
+    }
+    /// `^` of the exact mode: a negative integer exponent gives a rational instead
+    /// of a float.
+    pub fn pow_exact(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_integer() && other.is_integer() && other.as_bigint().is_negative() {
+            return Number::Rational(self.as_rational()).pow(other);
+        }
+        self.pow(other)
+    }
+    /// `/` of the exact mode: integers and rationals give a rational, which is
+    /// reduced to an integer when the division is exact.
+    pub fn div_exact(self, other: Self) -> Result<Number, ParserError> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        if self.is_complex() || other.is_complex() || self.is_float() || other.is_float() {
+            return self / other;
+        }
+        Ok(Number::from_rational(
+            self.as_rational() / other.as_rational(),
+        ))
+    }
+    /// `/` of the decimal mode: the quotient is rounded to `scale` digits after the
+    /// decimal point.
+    pub fn div_decimal(
+        self,
+        other: Self,
+        scale: u32,
+        rounding: Rounding,
+    ) -> Result<Number, ParserError> {
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        if self.is_complex() || other.is_complex() || self.is_float() || other.is_float() {
+            return self / other;
+        }
+        let quotient = self.as_rational() / other.as_rational();
+        Ok(Number::Decimal(
+            Decimal::from_rational(&quotient, scale, rounding).trim(),
+        ))
+    }
+    /// Integer division rounding towards negative infinity (floored division), so
+    /// `-7 // 2` is `-4`. Floats are floored as well and stay floats.
+    pub fn floor_div(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            return Err(not_ordered(&self, "//", &other));
+        }
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        if self.is_float() || other.is_float() {
+            return Ok(Number::F64((self.as_f64() / other.as_f64()).floor()));
+        }
+        if self.is_rational() || other.is_rational() || self.is_decimal() || other.is_decimal() {
+            let quotient = self.as_rational() / other.as_rational();
+            return Ok(Number::from_bigint(
+                quotient.floor().to_integer(),
+                32,
+                false,
+            ));
+        }
+        self.int_op(other, "//", |op1, op2| Some(op1.div_floor(&op2)))
+    }
+    /// Remainder of the floored division: the result has the sign of the divisor,
+    /// so `-7 % 2` is `1` and `7 % -2` is `-1`. In all cases `a == (a // b) * b + a % b`.
+    pub fn floor_mod(self, other: Self) -> Result<Number, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            return Err(not_ordered(&self, "%", &other));
+        }
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        if self.is_float() || other.is_float() {
+            let (op1, op2) = (self.as_f64(), other.as_f64());
+            return Ok(Number::F64(op1 - op2 * (op1 / op2).floor()));
+        }
+        if self.is_rational() || other.is_rational() {
+            let (op1, op2) = (self.as_rational(), other.as_rational());
+            let quotient = (&op1 / &op2).floor();
+            return Ok(Number::from_rational(op1 - op2 * quotient));
+        }
+        if self.is_decimal() || other.is_decimal() {
+            let (op1, op2) = (self.as_decimal(), other.as_decimal());
+            let scale = op1.scale().max(op2.scale());
+            let quotient = (op1.to_rational() / op2.to_rational()).floor();
+            // The remainder can't have more decimals than the operands, so this is exact.
+            let remainder = op1.to_rational() - op2.to_rational() * quotient;
+            return Ok(Number::Decimal(Decimal::from_rational(
+                &remainder,
+                scale,
+                Rounding::Truncate,
+            )));
+        }
+        self.int_op(other, "%", |op1, op2| Some(op1.mod_floor(&op2)))
+    }
+    /// Stores an integer in the narrowest type of at least `width` bits able to
+    /// hold it. Unsigned types are only tried when `unsigned` is set and come
+    /// before the signed type of the same width, `BigInt` is the last resort.
+    pub fn from_bigint(num: BigInt, width: u32, unsigned: bool) -> Number {
+        if width <= 32 {
+            if let Some(num) = num.to_u32().filter(|_| unsigned) {
+                return Number::U32(num);
+            }
+            if let Some(num) = num.to_i32() {
+                return Number::I32(num);
+            }
+        }
+        if width <= 64 {
+            if let Some(num) = num.to_u64().filter(|_| unsigned) {
+                return Number::U64(num);
+            }
+            if let Some(num) = num.to_i64() {
+                return Number::I64(num);
+            }
+        }
+        Number::BigInt(num)
+    }
+    /// Reduces a rational to an integer when its denominator is 1.
+    pub fn from_rational(num: BigRational) -> Number {
+        if num.is_integer() {
+            Number::from_bigint(num.to_integer(), 32, false)
+        } else {
+            Number::Rational(num)
+        }
+    }
+    fn is_float(&self) -> bool {
+        matches!(self, Number::F64(_))
+    }
+    fn is_rational(&self) -> bool {
+        matches!(self, Number::Rational(_))
+    }
+    fn is_complex(&self) -> bool {
+        matches!(self, Number::Complex(_))
+    }
+    fn is_decimal(&self) -> bool {
+        matches!(self, Number::Decimal(_))
+    }
+    pub fn is_integer(&self) -> bool {
+        !self.is_float() && !self.is_rational() && !self.is_complex() && !self.is_decimal()
+    }
+    fn is_unsigned(&self) -> bool {
+        matches!(self, Number::U32(_) | Number::U64(_))
+    }
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::F64(num) => *num == 0.0,
+            Number::Rational(num) => num.is_zero(),
+            Number::Complex(num) => num.is_zero(),
+            Number::Decimal(num) => num.is_zero(),
+            _ => self.as_bigint().is_zero(),
+        }
+    }
+    fn width(&self) -> u32 {
+        match self {
+            Number::U32(_) | Number::I32(_) | Number::F64(_) | Number::Complex(_) => 32,
+            Number::U64(_) | Number::I64(_) => 64,
+            Number::BigInt(_) | Number::Rational(_) | Number::Decimal(_) => u32::MAX,
+        }
+    }
+    /// Absolute value as a float, the modulus of a complex number.
+    pub fn magnitude(&self) -> f64 {
+        match self {
+            Number::Complex(num) => num.norm(),
+            num => num.as_f64().abs(),
+        }
+    }
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::U32(num) => *num as f64,
+            Number::I32(num) => *num as f64,
+            Number::U64(num) => *num as f64,
+            Number::I64(num) => *num as f64,
+            Number::BigInt(num) => num.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(num) => num.to_f64().unwrap_or(f64::NAN),
+            Number::Decimal(num) => num.to_f64(),
+            Number::F64(num) => *num,
+            Number::Complex(num) => num.re,
+        }
+    }
+    pub fn as_bigint(&self) -> BigInt {
+        match self {
+            Number::U32(num) => BigInt::from(*num),
+            Number::I32(num) => BigInt::from(*num),
+            Number::U64(num) => BigInt::from(*num),
+            Number::I64(num) => BigInt::from(*num),
+            Number::BigInt(num) => num.clone(),
+            Number::Rational(num) => num.to_integer(),
+            Number::Decimal(num) => num.to_integer(),
+            Number::F64(num) => BigInt::from(*num as i64),
+            Number::Complex(num) => BigInt::from(num.re as i64),
+        }
+    }
+    fn as_rational(&self) -> BigRational {
+        match self {
+            Number::Rational(num) => num.clone(),
+            Number::Decimal(num) => num.to_rational(),
+            Number::F64(num) => BigRational::from_float(*num).unwrap_or_else(BigRational::zero),
+            _ => BigRational::from_integer(self.as_bigint()),
+        }
+    }
+    fn as_decimal(&self) -> Decimal {
+        match self {
+            Number::Decimal(num) => num.clone(),
+            _ => Decimal::from(self.as_bigint()),
+        }
+    }
+    fn as_complex(&self) -> Complex64 {
+        match self {
+            Number::Complex(num) => *num,
+            _ => Complex64::new(self.as_f64(), 0.0),
+        }
+    }
+    /// Compares two numbers with a comparison operator. Numbers are compared by
+    /// value whatever their type, `1 == 1.0` is true. Complex numbers can only be
+    /// compared for equality.
+    pub fn compare(&self, op: BinaryOp, other: &Number) -> Result<bool, ParserError> {
+        if self.is_complex() || other.is_complex() {
+            let equal = self.as_complex() == other.as_complex();
+            return match op {
+                BinaryOp::Eq => Ok(equal),
+                BinaryOp::Ne => Ok(!equal),
+                _ => Err(not_ordered(self, op.lexeme(), other)),
+            };
+        }
+        // Floats are compared as floats so that `nan` is equal to nothing.
+        let ordering = if self.is_float() || other.is_float() {
+            self.as_f64().partial_cmp(&other.as_f64())
+        } else {
+            Some(self.as_rational().cmp(&other.as_rational()))
+        };
+        Ok(match op {
+            BinaryOp::Eq => ordering == Some(Ordering::Equal),
+            BinaryOp::Ne => ordering != Some(Ordering::Equal),
+            BinaryOp::Lt => ordering == Some(Ordering::Less),
+            BinaryOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            BinaryOp::Gt => ordering == Some(Ordering::Greater),
+            BinaryOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            _ => unreachable!(),
+        })
+    }
+    /// Writes an integer in the given radix, with the prefix of the matching literal
+    /// when there is one: `255` in base 16 is `0xff`, in base 36 it is `36#73`.
+    /// Negative `I32` and `I64` are written in two's complement, so `-1` in base 16
+    /// is `0xffffffff`. Other numbers can't be written in a radix and give `None`.
+    pub fn to_radix(&self, radix: u32) -> Option<String> {
+        let num = match self {
+            Number::I32(num) if radix != 10 => BigInt::from(*num as u32),
+            Number::I64(num) if radix != 10 => BigInt::from(*num as u64),
+            num if num.is_integer() => num.as_bigint(),
+            _ => return None,
+        };
+        let sign = if num.is_negative() { "-" } else { "" };
+        let digits = num.magnitude().to_str_radix(radix);
+        Some(match radix {
+            2 => format!("{}0b{}", sign, digits),
+            8 => format!("{}0o{}", sign, digits),
+            10 => format!("{}{}", sign, digits),
+            16 => format!("{}0x{}", sign, digits),
+            _ => format!("{}{}#{}", sign, radix, digits),
+        })
+    }
+    /// Like `int_op`, but fails unless both operands are integers.
+    fn bitwise_op(
+        self,
+        other: Self,
+        operator: &str,
+        op: impl Fn(BigInt, BigInt) -> Option<BigInt>,
+    ) -> Result<Number, ParserError> {
+        if let Some(operand) = [&self, &other].iter().find(|operand| !operand.is_integer()) {
+            return Err(not_integer(operator, operand));
+        }
+        if other.as_bigint().is_negative() && (operator == "<<" || operator == ">>") {
+            return Err(ParserError::newr(
+                "".to_string(),
+                format!("Cannot shift by a negative amount: {}", other),
+            ));
+        }
+        self.int_op(other, operator, op)
+    }
+    /// Applies `op` on the exact value of two integers, the result is widened as
+    /// much as needed, see `Number::from_bigint`.
+    fn int_op(
+        self,
+        other: Self,
+        operator: &str,
+        op: impl Fn(BigInt, BigInt) -> Option<BigInt>,
+    ) -> Result<Number, ParserError> {
+        let width = self.width().max(other.width());
+        let unsigned = self.is_unsigned() && other.is_unsigned();
+        op(self.as_bigint(), other.as_bigint())
+            .map(|result| Number::from_bigint(result, width, unsigned))
+            .ok_or_else(|| overflow(&self, operator, &other))
+    }
+}
+/// Number of significant digits floats are displayed with, when the formatter
+/// doesn't specify a precision.
+pub const DEFAULT_PRECISION: usize = 15;
+/// The precision of the formatter is the number of significant digits floats are
+/// displayed with, integers and decimals are always displayed entirely.
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::U32(num) => write!(f, "{}", num),
+            Number::I32(num) => write!(f, "{}", num),
+            Number::U64(num) => write!(f, "{}", num),
+            Number::I64(num) => write!(f, "{}", num),
+            Number::BigInt(num) => write!(f, "{}", num),
+            Number::Rational(num) => write!(f, "{}", num),
+            Number::Decimal(num) => write!(f, "{}", num),
+            Number::F64(num) => write!(
+                f,
+                "{}",
+                format_float(*num, f.precision().unwrap_or(DEFAULT_PRECISION))
+            ),
+            Number::Complex(num) => {
+                // The parts are always floats, the `.0` would only be noise.
+                let part = |part: f64| {
+                    let part = format_float(part, f.precision().unwrap_or(DEFAULT_PRECISION));
+                    part.trim_end_matches(".0").to_string()
+                };
+                if num.re == 0.0 {
+                    write!(f, "{}i", part(num.im))
+                } else if num.im.is_sign_negative() {
+                    write!(f, "{}-{}i", part(num.re), part(-num.im))
+                } else {
+                    write!(f, "{}+{}i", part(num.re), part(num.im))
+                }
+            }
+        }
+    }
+}
+/// Floats are displayed in scientific notation, as in `6.02e23`, when their
+/// exponent is outside of `SCIENTIFIC_BELOW..SCIENTIFIC_FROM`.
+const SCIENTIFIC_BELOW: i32 = -5;
+const SCIENTIFIC_FROM: i32 = 16;
+/// Rounds `num` to `digits` significant digits, dropping the trailing zeros but
+/// keeping a `.0` so that floats don't look like integers.
+fn format_float(num: f64, digits: usize) -> String {
+    if !num.is_finite() {
+        return num.to_string();
+    }
+    let digits = digits.max(1);
+    // The exponent has to be taken after rounding, `9.99` to two digits is `10`.
+    let scientific = format!("{:.*e}", digits - 1, num);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent = exponent[1..].parse::<i32>().unwrap();
+    // Far from 1 the positional notation would be mostly zeros.
+    if !(SCIENTIFIC_BELOW..SCIENTIFIC_FROM).contains(&exponent) && num != 0.0 {
+        let mantissa = if mantissa.contains('.') {
+            mantissa.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            mantissa
+        };
+        return format!("{}e{}", mantissa, exponent);
+    }
+    let rounded = scientific.parse::<f64>().unwrap();
+    let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+    let mut positional = format!("{:.*}", decimals, rounded);
+    if positional.contains('.') {
+        let trimmed = positional.trim_end_matches('0').len();
+        positional.truncate(trimmed);
+    }
+    if positional.ends_with('.') || !positional.contains('.') {
+        positional = format!("{}.0", positional.trim_end_matches('.'));
+    }
+    positional
+}
+fn division_by_zero() -> ParserError {
+    ParserError::newr("".to_string(), "Division by zero".to_string())
+}
+fn not_ordered(operand1: &Number, operator: &str, operand2: &Number) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!(
+            "Cannot apply operator {:#?} to complex numbers {} and {}",
+            operator, operand1, operand2
+        ),
+    )
+}
+fn not_integer(operator: &str, operand: &Number) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!(
+            "Operator {:#?} only applies to integers, found {}",
+            operator, operand
+        ),
+    )
+}
+fn overflow(operand1: &Number, operator: &str, operand2: &Number) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!(
+            "Overflow while computing {} {} {}",
+            operand1, operator, operand2
+        ),
+    )
+}
+/// Literals are also the values expressions evaluate to.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Num(Number),
+    Bool(bool),
+    Function(Rc<Function>),
+    List(Vec<Literal>),
 }
+/// A user defined function, `fn hyp(a, b) = sqrt(a*a + b*b);`, or a lambda, which
+/// is named after the variable it is first bound to, if any.
 #[derive(Debug, Clone, PartialEq)]
-pub enum Atom {
-    Lit(Literal),
-    Var(String),
-    Op(Operator),
-    Parens(Expr),
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Expr,
+    /// Values of the variables a lambda reads, taken when it is created.
+    pub captured: HashMap<String, Literal>,
 }
-#[derive(Debug, Clone, PartialEq)]
-pub struct Operator {
-    lexeme: String,
-    precedence: i32,
-}
-fn take_digit(s: String) -> Result<(String, char), ParserError> {
-    let mut chars = s.chars();
-    let first = chars.next();
-    first
-        .filter(|x| x.is_digit(10))
-        .ok_or_else(|| ParserError::new(s.clone()))
-        .and_then(|x| Ok((chars.collect::<String>(), x)))
-}
-pub fn take_float(s: String) -> Result<(String, Atom), ParserError> {
-    let mut float = String::new();
-    let (remaining, _) = take_while1(s.clone(), |x| take_digit(x))
-        .and_then(|(remaining, int)| {
-            float.push_str(int.into_iter().collect::<String>().as_str());
-            take_char(remaining, '.')
-        })
-        .and_then(|(remaining, c)| {
-            float.push(c);
-            take_while1(remaining, |x| take_digit(x))
-        })
-        .and_then(|(remaining, decimals)| {
-            float.push_str(decimals.into_iter().collect::<String>().as_str());
-            Ok((remaining, ()))
-        })
-        .map_err(|_| ParserError::newr(s, format!("Invalid float literal")))?;
-    Ok((
-        remaining,
-        Atom::Lit(Literal::Num(Number::F32(float.parse::<f32>().unwrap()))),
-    ))
+impl Function {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("lambda")
+    }
 }
-pub fn take_int(s: String) -> Result<(String, Atom), ParserError> {
-    let (remaining, num) = take_while1(s.clone(), |x| take_digit(x))
-        .map_err(|_| ParserError::newr(s, format!("Invalid int literal")))?;
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Num(num) => fmt::Display::fmt(num, f),
+            Literal::Bool(value) => write!(f, "{}", value),
+            Literal::Function(function) => match &function.name {
+                Some(name) => write!(f, "fn {}({})", name, function.params.join(", ")),
+                None => write!(f, "fn({})", function.params.join(", ")),
+            },
+            Literal::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match f.precision() {
+                        Some(precision) => write!(f, "{:.*}", precision, item)?,
+                        None => write!(f, "{}", item)?,
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+/// Unwraps a number, `context` names what needed it for the error message.
+pub fn expect_number(value: Literal, context: &str) -> Result<Number, ParserError> {
+    match value {
+        Literal::Num(num) => Ok(num),
+        value => Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects a number, found {}", context, value),
+        )),
+    }
+}
+pub fn expect_function(value: Literal, context: &str) -> Result<Rc<Function>, ParserError> {
+    match value {
+        Literal::Function(function) => Ok(function),
+        value => Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects a function, found {}", context, value),
+        )),
+    }
+}
+pub fn expect_list(value: Literal, context: &str) -> Result<Vec<Literal>, ParserError> {
+    match value {
+        Literal::List(items) => Ok(items),
+        value => Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects a list, found {}", context, value),
+        )),
+    }
+}
+pub fn expect_bool(value: Literal, context: &str) -> Result<bool, ParserError> {
+    match value {
+        Literal::Bool(value) => Ok(value),
+        value => Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects a boolean, found {}", context, value),
+        )),
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    BitNot,
+    Not,
+}
+impl UnaryOp {
+    /// Prefix operators bind tighter than `*` but looser than `^`, so `-2 ^ 2` is `-(2 ^ 2)`.
+    pub const PRECEDENCE: i32 = 12;
+    /// Precedence of the operand of the operator: `not` applies to a whole
+    /// comparison, `not a == b` is `not (a == b)`.
+    pub fn precedence(self) -> i32 {
+        match self {
+            UnaryOp::Not => 4,
+            _ => UnaryOp::PRECEDENCE,
+        }
+    }
+    pub fn lexeme(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::BitNot => "~",
+            UnaryOp::Not => "not",
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    Xor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+impl BinaryOp {
+    pub fn precedence(self) -> i32 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            // Comparisons bind looser than bitwise operators, unlike in C, so that
+            // `x & 1 == 0` means `(x & 1) == 0`.
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 4,
+            // As in C, bitwise operators bind looser than arithmetic ones.
+            BinaryOp::BitOr => 5,
+            BinaryOp::Xor => 6,
+            BinaryOp::BitAnd => 7,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Mod => 10,
+            BinaryOp::Pow => 15,
+        }
+    }
+    /// Right associative operators group from the right, `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+    pub fn is_right_assoc(self) -> bool {
+        self == BinaryOp::Pow
+    }
+    pub fn is_bitwise(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::Xor | BinaryOp::Shl | BinaryOp::Shr
+        )
+    }
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+        )
+    }
+    pub fn lexeme(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::FloorDiv => "//",
+            BinaryOp::Mod => "%",
+            BinaryOp::Pow => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        }
+    }
+}
+/// Takes the digits of a literal in the given radix. Digits can be separated by
+/// `_`, as in `1_000_000`, the separators are dropped from the result.
+fn take_digits(s: String, radix: u32) -> Result<(String, String), ParserError> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut digits = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_digit(radix) {
+            digits.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        // A separator has to be between two digits.
+        let next = chars[i..].iter().position(|c| *c != '_').map(|pos| i + pos);
+        match next {
+            Some(next) if chars[i] == '_' && !digits.is_empty() && chars[next].is_digit(radix) => {
+                i = next
+            }
+            _ => break,
+        }
+    }
+    if digits.is_empty() {
+        return Err(ParserError::new(s));
+    }
+    Ok((chars[i..].iter().collect(), digits))
+}
+/// Float literals are parsed as exact decimals, `eval` turns them into a `F64`
+/// unless the session is in decimal mode.
+pub fn take_float(s: String) -> Result<(String, Number), ParserError> {
+    // The integer part can be left out, as in `.5`.
+    let (remaining, int) =
+        take_digits(s.clone(), 10).unwrap_or_else(|_| (s.clone(), String::new()));
+    let (remaining, decimals) = take_char(remaining, '.')
+        .and_then(|(remaining, _)| take_digits(remaining, 10))
+        .map_err(|_| ParserError::newr(s, "Invalid float literal".to_string()))?;
+    let mantissa = format!("{}{}", int, decimals).parse().unwrap();
     Ok((
         remaining,
-        Atom::Lit(Literal::Num(Number::I32(
-            num.into_iter().collect::<String>().parse().unwrap(),
-        ))),
+        Number::Decimal(Decimal::new(mantissa, decimals.len() as u32)),
     ))
 }
-pub fn take_numbers(s: String) -> Result<(String, Atom), ParserError> {
-    let mut number = Atom::Lit(Literal::Num(Number::I32(0)));
-    take_float(s.clone())
-        .or_else(|error| take_int(error.remaining()))
-        .and_then(|(remaining, num)| {
-            number = num;
-            Ok((take_whitespaces0(remaining)?.0, ()))
-        })
-        .and_then(|(remaining, _)| Ok((remaining, number)))
-        .map_err(|_| ParserError::new(s))
+/// Biggest exponent allowed in a float literal, way past the range of `f64` but
+/// small enough for the exact value to be stored.
+const MAX_EXPONENT: i32 = 10_000;
+/// An exponent after a decimal number, as in `6.02e23` or `1.5E-3`, makes it a
+/// float literal even when the mantissa is an integer. `literal` is the input the
+/// number started at, to report malformed exponents.
+fn take_exponent(s: String, num: Number, literal: &str) -> Result<(String, Number), ParserError> {
+    let remaining = match take_char(s.clone(), 'e').or_else(|_| take_char(s.clone(), 'E')) {
+        Ok((remaining, _)) => remaining,
+        Err(_) => return Ok((s, num)),
+    };
+    let (remaining, sign) = take_char(remaining.clone(), '-')
+        .or_else(|_| take_char(remaining.clone(), '+'))
+        .unwrap_or((remaining, '+'));
+    let (remaining, digits) = take_digits(remaining.clone(), 10).map_err(|_| {
+        let parsed = &literal[..literal.len() - remaining.len()];
+        let found = match remaining.chars().next() {
+            Some(c) => format!("{:#?}", c),
+            None => "nothing".to_string(),
+        };
+        ParserError::newr(
+            remaining.clone(),
+            format!(
+                "Malformed exponent in {:#?}: expected digits, found {}",
+                parsed, found
+            ),
+        )
+    })?;
+    let parsed = &literal[..literal.len() - remaining.len()];
+    let exponent = digits
+        .parse::<i32>()
+        .ok()
+        .filter(|exponent| *exponent <= MAX_EXPONENT)
+        .ok_or_else(|| {
+            ParserError::newr(
+                remaining.clone(),
+                format!(
+                    "Exponent out of range in {:#?}, it can be at most {}",
+                    parsed, MAX_EXPONENT
+                ),
+            )
+        })?;
+    let exponent = if sign == '-' { -exponent } else { exponent };
+    let mantissa = match num {
+        Number::Decimal(num) => num,
+        num => Decimal::from(num.as_bigint()),
+    };
+    Ok((remaining, Number::Decimal(mantissa.shift(exponent))))
+}
+/// Integer literals are decimal unless prefixed with `0x` (hexadecimal), `0o`
+/// (octal) or `0b` (binary).
+pub fn take_int(s: String) -> Result<(String, Number), ParserError> {
+    let (remaining, radix, name) = take_str(s.clone(), "0x")
+        .map(|(remaining, _)| (remaining, 16, "hexadecimal"))
+        .or_else(|_| take_str(s.clone(), "0o").map(|(remaining, _)| (remaining, 8, "octal")))
+        .or_else(|_| take_str(s.clone(), "0b").map(|(remaining, _)| (remaining, 2, "binary")))
+        .unwrap_or((s.clone(), 10, "int"));
+    let (remaining, digits) = take_digits(remaining, radix)
+        .map_err(|_| ParserError::newr(s.clone(), format!("Invalid {} literal", name)))?;
+    // `0b102` must not be read as `0b10` followed by `2`.
+    if let Some(c) = remaining.chars().next().filter(|c| c.is_alphanumeric()) {
+        if radix != 10 && c != 'i' {
+            return Err(ParserError::newr(
+                s,
+                format!("Invalid digit {:#?} in {} literal", c, name),
+            ));
+        }
+    }
+    let num = BigInt::parse_bytes(digits.as_bytes(), radix).unwrap();
+    Ok((remaining, Number::from_bigint(num, 32, false)))
+}
+/// An `i` right after a number makes it imaginary, as in `4i` or `2.5i`.
+fn take_imaginary(s: String, num: Number) -> (String, Number) {
+    match take_char(s.clone(), 'i') {
+        Ok((remaining, _)) if take_alphanumeric(remaining.clone()).is_err() => (
+            remaining,
+            Number::Complex(Complex64::new(0.0, num.as_f64())),
+        ),
+        _ => (s, num),
+    }
+}
+pub fn take_numbers(s: String) -> Result<(String, Number), ParserError> {
+    let (remaining, num) = take_float(s.clone()).or_else(|error| take_int(error.remaining()))?;
+    let (remaining, num) = take_exponent(remaining, num, &s)?;
+    let (remaining, num) = take_imaginary(remaining, num);
+    Ok((take_whitespaces0(remaining)?.0, num))
 }
 
-pub fn take_operator(s: String) -> Result<(String, Atom), ParserError> {
+pub fn take_operator(s: String) -> Result<(String, BinaryOp), ParserError> {
     take_str(s.clone(), "+")
+        .or_else(|error| take_str(error.remaining(), "**"))
         .or_else(|error| take_str(error.remaining(), "*"))
+        .or_else(|error| take_str(error.remaining(), "^"))
+        .or_else(|error| take_str(error.remaining(), "//"))
         .or_else(|error| take_str(error.remaining(), "/"))
+        .or_else(|error| take_str(error.remaining(), "%"))
         .or_else(|error| take_str(error.remaining(), "-"))
+        .or_else(|error| take_str(error.remaining(), "&"))
+        .or_else(|error| take_str(error.remaining(), "|"))
+        .or_else(|error| take_keyword(error.remaining(), "xor"))
+        .or_else(|error| take_str(error.remaining(), "<<"))
+        .or_else(|error| take_str(error.remaining(), ">>"))
+        .or_else(|error| take_str(error.remaining(), "=="))
+        .or_else(|error| take_str(error.remaining(), "!="))
+        .or_else(|error| take_str(error.remaining(), "<="))
+        .or_else(|error| take_str(error.remaining(), ">="))
+        .or_else(|error| take_str(error.remaining(), "<"))
+        .or_else(|error| take_str(error.remaining(), ">"))
+        .or_else(|error| take_keyword(error.remaining(), "and"))
+        .or_else(|error| take_keyword(error.remaining(), "or"))
         .and_then(|(remaining, op)| match op.as_str() {
-            "+" => Ok((
-                remaining,
-                Atom::Op(Operator {
-                    lexeme: op,
-                    precedence: 5,
-                }),
-            )),
-            "*" => Ok((
-                remaining,
-                Atom::Op(Operator {
-                    lexeme: op,
-                    precedence: 10,
-                }),
-            )),
-            "/" => Ok((
-                remaining,
-                Atom::Op(Operator {
-                    lexeme: op,
-                    precedence: 10,
-                }),
-            )),
-            "-" => Ok((
-                remaining,
-                Atom::Op(Operator {
-                    lexeme: op,
-                    precedence: 5,
-                }),
-            )),
+            "+" => Ok((remaining, BinaryOp::Add)),
+            "*" => Ok((remaining, BinaryOp::Mul)),
+            "^" | "**" => Ok((remaining, BinaryOp::Pow)),
+            "//" => Ok((remaining, BinaryOp::FloorDiv)),
+            "/" => Ok((remaining, BinaryOp::Div)),
+            "%" => Ok((remaining, BinaryOp::Mod)),
+            "-" => Ok((remaining, BinaryOp::Sub)),
+            "&" => Ok((remaining, BinaryOp::BitAnd)),
+            "|" => Ok((remaining, BinaryOp::BitOr)),
+            "xor" => Ok((remaining, BinaryOp::Xor)),
+            "<<" => Ok((remaining, BinaryOp::Shl)),
+            ">>" => Ok((remaining, BinaryOp::Shr)),
+            "==" => Ok((remaining, BinaryOp::Eq)),
+            "!=" => Ok((remaining, BinaryOp::Ne)),
+            "<=" => Ok((remaining, BinaryOp::Le)),
+            ">=" => Ok((remaining, BinaryOp::Ge)),
+            "<" => Ok((remaining, BinaryOp::Lt)),
+            ">" => Ok((remaining, BinaryOp::Gt)),
+            "and" => Ok((remaining, BinaryOp::And)),
+            "or" => Ok((remaining, BinaryOp::Or)),
             _ => Err(ParserError::newr(s, format!("Unknwon operator: {}", op))),
         })
 }
 
-pub fn into_postfix(tokens: Expr) -> Result<Expr, ParserError> {
-    let mut op_stack: Expr = vec![];
-    let mut output = vec![];
-    for i in tokens {
-        match i {
-            Atom::Lit(lit) => output.push(Atom::Lit(lit)),
-            Atom::Var(ident) => output.push(Atom::Var(ident)),
-            Atom::Op(op) => {
-                while op_stack.last().is_some() {
-                    let last = op_stack.last().unwrap();
-                    if let Atom::Op(last_op) = last {
-                        if last_op.precedence >= op.precedence {
-                            output.push(op_stack.pop().unwrap());
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                op_stack.push(Atom::Op(op));
-            }
-            Atom::Parens(expr) => into_postfix(expr)?.into_iter().for_each(|x| output.push(x)),
-        }
-    }
-    for i in op_stack.into_iter().rev() {
-        output.push(i);
-    }
-    Ok(output)
+pub fn take_prefix_operator(s: String) -> Result<(String, UnaryOp), ParserError> {
+    take_str(s.clone(), "-")
+        .or_else(|error| take_str(error.remaining(), "+"))
+        .or_else(|error| take_str(error.remaining(), "~"))
+        .or_else(|error| take_keyword(error.remaining(), "not"))
+        .and_then(|(remaining, op)| Ok((take_whitespaces0(remaining)?.0, op)))
+        .map(|(remaining, op)| match op.as_str() {
+            "-" => (remaining, UnaryOp::Neg),
+            "~" => (remaining, UnaryOp::BitNot),
+            "not" => (remaining, UnaryOp::Not),
+            _ => (remaining, UnaryOp::Plus),
+        })
 }
+
+/// Checks the operands of an operator. Booleans can only be compared with `==`
+/// and `!=`, and never mixed with numbers. Numbers of different types are only
+/// accepted when one can be promoted to the other without changing its category:
+/// ints, floats and complex numbers, this is what the strict mode enforces.
 pub fn type_check_op(
-    operand1: Number,
+    operand1: Literal,
     operator: String,
-    operand2: Number,
+    operand2: Literal,
 ) -> Result<Type, ParserError> {
+    let (operand1, operand2) = match (operand1, operand2) {
+        (Literal::Num(operand1), Literal::Num(operand2)) => (operand1, operand2),
+        (Literal::Bool(_), Literal::Bool(_)) if operator == "==" || operator == "!=" => {
+            return Ok(Type::Bool)
+        }
+        (operand1, operand2) => {
+            return Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "Cannot apply operator {:#?} between {} and {}",
+                    operator, operand1, operand2
+                ),
+            ))
+        }
+    };
+    // Bitwise operators work on the bits of integers, whatever the mode.
+    if ["&", "|", "xor", "<<", ">>"].contains(&operator.as_str()) {
+        if let Some(operand) = [&operand1, &operand2]
+            .iter()
+            .find(|operand| !operand.is_integer())
+        {
+            return Err(not_integer(&operator, operand));
+        }
+    }
+    if operand1.is_complex() || operand2.is_complex() {
+        return Ok(Type::Complex);
+    }
     match operand1 {
-        Number::F32(num) => match operand2 {
-            Number::F32(num) => Ok(Type::F32),
-            _ => {
-                return Err(ParserError::newr(
-                    "".to_string(),
-                    format!(
-                        "Cannot apply operator {:#?} between {:#?} and {:#?}",
-                        operator, operand1, operand2
-                    ),
-                ))
-            }
+        Number::F64(_) | Number::Decimal(_) => match operand2 {
+            Number::F64(_) => Ok(Type::F64),
+            Number::Decimal(_) => Ok(Type::Decimal),
+            _ => Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "Cannot apply operator {:#?} between {} and {}",
+                    operator, operand1, operand2
+                ),
+            )),
         },
         _ => match operand2 {
-            Number::F32(num) => {
-                return Err(ParserError::newr(
-                    "".to_string(),
-                    format!(
-                        "Cannot apply operator {:#?} between {:#?} and {:#?}",
-                        operator, operand1, operand2
-                    ),
-                ))
-            }
+            Number::F64(_) | Number::Decimal(_) => Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "Cannot apply operator {:#?} between {} and {}",
+                    operator, operand1, operand2
+                ),
+            )),
             Number::I32(_) => Ok(Type::I32),
             Number::U32(_) => Ok(Type::U32),
+            Number::I64(_) => Ok(Type::I64),
+            Number::U64(_) => Ok(Type::U64),
+            Number::BigInt(_) => Ok(Type::BigInt),
+            Number::Rational(_) => Ok(Type::Rational),
+            Number::Complex(_) => Ok(Type::Complex),
         },
     }
 }
-pub fn eval_postfix(vec: Expr, variables: &mut HashMap<String, f32>) -> Result<f32, ParserError> {
-    let mut stack: Expr = vec![];
-    for i in vec {
-        match i {
-            Atom::Op(op) => {
-                let operand2 = match stack.pop() {
-                    Some(Atom::Lit(Literal::Num(num))) => num,
-                    _ => {
-                        return Err(ParserError::newr(
-                            "".to_string(),
-                            format!("Expression wasn't valid"),
-                        ))
-                    }
-                };
-                let operand1 = match stack.pop() {
-                    Some(Atom::Lit(Literal::Num(num))) => num,
-                    _ => {
-                        return Err(ParserError::newr(
-                            "".to_string(),
-                            format!("Expression wasn't valid"),
-                        ))
-                    }
-                };
-                let expr_type =
-                    type_check_op(operand1.clone(), op.lexeme.clone(), operand2.clone())?;
-                match op.lexeme.as_str() {
-                    "+" => match expr_type {
-                        Type::I32 => stack.push(Atom::Lit(Literal::Num(Number::I32(
-                            (operand1 + operand2) as i32,
-                        )))),
-                        Type::F32 => {
-                            stack.push(Atom::Lit(Literal::Num(Number::F32(operand1 + operand2))))
-                        }
-                        Type::U32 => stack.push(Atom::Lit(Literal::Num(Number::U32(
-                            (operand1 + operand2) as u32,
-                        )))),
-                    },
-                    "*" => match expr_type {
-                        Type::I32 => stack.push(Atom::Lit(Literal::Num(Number::I32(
-                            (operand1 * operand2) as i32,
-                        )))),
-                        Type::F32 => {
-                            stack.push(Atom::Lit(Literal::Num(Number::F32(operand1 * operand2))))
-                        }
-                        Type::U32 => stack.push(Atom::Lit(Literal::Num(Number::U32(
-                            (operand1 * operand2) as u32,
-                        )))),
-                    },
-                    "/" => {
-                        match expr_type {
-                            Type::I32 => stack
-                                .push(Atom::Lit(Literal::Num(Number::F32(operand1 / operand2)))),
-                            Type::F32 => stack
-                                .push(Atom::Lit(Literal::Num(Number::F32(operand1 / operand2)))),
-                            Type::U32 => stack
-                                .push(Atom::Lit(Literal::Num(Number::F32(operand1 / operand2)))),
-                        }
-                    }
-                    "-" => match expr_type {
-                        Type::I32 => stack.push(Atom::Lit(Literal::Num(Number::I32(
-                            (operand1 - operand2) as i32,
-                        )))),
-                        Type::F32 => {
-                            stack.push(Atom::Lit(Literal::Num(Number::F32(operand1 - operand2))))
-                        }
-                        Type::U32 => stack.push(Atom::Lit(Literal::Num(Number::U32(
-                            (operand1 - operand2) as u32,
-                        )))),
-                    },
-                    _ => {
-                        return Err(ParserError::newr(
-                            "".to_string(),
-                            format!("Unknwon operator: {:#?}", op),
-                        ))
-                    }
-                }
-            }
-            Atom::Var(value) => match variables.get(&value) {
-                Some(num) => stack.push(Atom::Lit(Literal::Num(Number::F32(*num as f32)))),
-                None => {
-                    return Err(ParserError::newr(
-                        "".to_string(),
-                        format!("Undefined variable: {:#?}", value),
-                    ))
-                }
-            },
-            _ => stack.push(i),
+/// The variables an expression can see: the parameters of the function being
+/// called, then the variables of the session and the constants.
+pub struct Scope<'a> {
+    session: &'a Session,
+    locals: HashMap<String, Literal>,
+    /// Number of nested calls, bounded by `Settings::max_depth`.
+    depth: usize,
+}
+impl<'a> Scope<'a> {
+    pub fn new(session: &'a Session) -> Self {
+        Self {
+            session,
+            locals: HashMap::new(),
+            depth: 0,
         }
     }
-    if stack.len() == 1 {
-        match stack[0].clone() {
-            Atom::Lit(Literal::Num(num)) => match num {
-                Number::I32(num) => Ok(num as f32),
-                Number::F32(num) => Ok(num),
-                Number::U32(num) => Ok(num as f32),
-            },
-            _ => {
-                return Err(ParserError::newr(
-                    "".to_string(),
-                    format!("Syntax Error: Unknown"),
-                ))
+    /// A scope where `name` is bound to `value`, for the index of `sum` and `prod`.
+    fn with_local(&self, name: &str, value: Literal) -> Scope<'a> {
+        let mut locals = self.locals.clone();
+        locals.insert(name.to_string(), value);
+        Scope {
+            session: self.session,
+            locals,
+            depth: self.depth,
+        }
+    }
+    pub fn settings(&self) -> &Settings {
+        &self.session.settings
+    }
+    fn get(&self, ident: &str) -> Option<Literal> {
+        self.get_variable(ident)
+            .or_else(|| constant(ident).map(Literal::Num))
+    }
+    fn get_variable(&self, ident: &str) -> Option<Literal> {
+        self.locals
+            .get(ident)
+            .or_else(|| self.session.variables.get(ident))
+            .cloned()
+    }
+    /// Calls a user function. Its body only sees its parameters, its own name, the
+    /// variables it captured if it is a lambda and the session, not the locals of
+    /// the caller.
+    pub fn call(
+        &self,
+        function: &Rc<Function>,
+        args: Vec<Literal>,
+    ) -> Result<Literal, ParserError> {
+        if args.len() != function.params.len() {
+            return Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "{} expects {} argument(s), found {}",
+                    function.name(),
+                    function.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        let max_depth = self.session.settings.max_depth;
+        if self.depth >= max_depth {
+            return Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "Maximum recursion depth of {} exceeded while calling {}",
+                    max_depth,
+                    function.name()
+                ),
+            ));
+        }
+        let mut locals = function.captured.clone();
+        if let Some(name) = &function.name {
+            locals.insert(name.clone(), Literal::Function(function.clone()));
+        }
+        locals.extend(function.params.iter().cloned().zip(args));
+        let scope = Scope {
+            session: self.session,
+            locals,
+            depth: self.depth + 1,
+        };
+        eval(&function.body, &scope)
+    }
+}
+pub fn eval(expr: &Expr, scope: &Scope) -> Result<Literal, ParserError> {
+    match expr {
+        Expr::Literal(Literal::Num(Number::Decimal(num))) => {
+            Ok(Literal::Num(match scope.session.settings.decimal {
+                Some(scale) => Number::Decimal(num.round(scale, scope.session.settings.rounding)),
+                None => Number::F64(num.to_f64()),
+            }))
+        }
+        Expr::Literal(literal) => Ok(literal.clone()),
+        Expr::Var(ident) => scope.get(ident).ok_or_else(|| {
+            ParserError::newr("".to_string(), format!("Undefined variable: {:#?}", ident))
+        }),
+        Expr::Unary(UnaryOp::Not, operand) => {
+            let operand = expect_bool(eval(operand, scope)?, "Operator \"not\"")?;
+            Ok(Literal::Bool(!operand))
+        }
+        Expr::Unary(op, operand) => {
+            let context = format!("Operator {:#?}", op.lexeme());
+            let operand = expect_number(eval(operand, scope)?, &context)?;
+            match op {
+                UnaryOp::Neg => -operand,
+                UnaryOp::Plus => Ok(operand),
+                UnaryOp::BitNot => !operand,
+                UnaryOp::Not => unreachable!(),
             }
+            .map(Literal::Num)
+        }
+        // `and` and `or` only evaluate their right operand when it decides the result.
+        Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), operand1, operand2) => {
+            let context = format!("Operator {:#?}", op.lexeme());
+            let operand1 = expect_bool(eval(operand1, scope)?, &context)?;
+            if operand1 == (*op == BinaryOp::Or) {
+                return Ok(Literal::Bool(operand1));
+            }
+            Ok(Literal::Bool(expect_bool(
+                eval(operand2, scope)?,
+                &context,
+            )?))
+        }
+        Expr::Binary(op, operand1, operand2) => {
+            let operand1 = eval(operand1, scope)?;
+            let operand2 = eval(operand2, scope)?;
+            eval_binary(*op, operand1, operand2, &scope.session.settings)
+        }
+        Expr::Call(callee, args) => match &**callee {
+            Expr::Var(name) => call_named(name, args, scope),
+            callee => {
+                let function = expect_function(eval(callee, scope)?, "Call")?;
+                scope.call(&function, eval_args(args, scope)?)
+            }
+        },
+        Expr::If(cond, then, otherwise) => {
+            if expect_bool(eval(cond, scope)?, "\"if\"")? {
+                eval(then, scope)
+            } else {
+                eval(otherwise, scope)
+            }
+        }
+        Expr::Lambda(params, body) => {
+            let mut vars = vec![];
+            body.free_vars(params, &mut vars);
+            let captured = vars
+                .into_iter()
+                .filter_map(|var| Some((var.clone(), scope.get_variable(&var)?)))
+                .collect();
+            Ok(Literal::Function(Rc::new(Function {
+                name: None,
+                params: params.clone(),
+                body: (**body).clone(),
+                captured,
+            })))
+        }
+        Expr::List(items) => items
+            .iter()
+            .map(|item| eval(item, scope))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Literal::List),
+    }
+}
+/// Calls the variable `name` if it holds a function, and the builtin otherwise.
+fn call_named(name: &str, args: &[Expr], scope: &Scope) -> Result<Literal, ParserError> {
+    if let ("sum" | "prod", [Expr::Var(index), from, to, body]) = (name, args) {
+        if scope.get(name).is_none() {
+            let (from, to) = (eval(from, scope)?, eval(to, scope)?);
+            return series(name, from, to, scope.settings(), |value| {
+                eval(body, &scope.with_local(index, value))
+            });
         }
-    } else {
-        return Err(ParserError::newr(
-            "".to_string(),
-            format!("Syntax Error: Unknown"),
-        ));
+    }
+    let args = eval_args(args, scope)?;
+    if let Some(Literal::Function(function)) = scope.get(name) {
+        return scope.call(&function, args);
+    }
+    call_builtin(name, args, scope)
+}
+fn eval_args(args: &[Expr], scope: &Scope) -> Result<Vec<Literal>, ParserError> {
+    args.iter().map(|arg| eval(arg, scope)).collect()
+}
+pub fn eval_binary(
+    op: BinaryOp,
+    operand1: Literal,
+    operand2: Literal,
+    settings: &Settings,
+) -> Result<Literal, ParserError> {
+    let (operand1, operand2) = match (operand1, operand2) {
+        (Literal::Num(operand1), Literal::Num(operand2)) => (operand1, operand2),
+        (operand1 @ Literal::List(_), operand2) | (operand1, operand2 @ Literal::List(_)) => {
+            return eval_list_op(op, operand1, operand2, settings)
+        }
+        (operand1, operand2) => {
+            type_check_op(operand1.clone(), op.lexeme().to_string(), operand2.clone())?;
+            // Only booleans are left, and they can only be compared for equality.
+            return Ok(Literal::Bool(
+                (operand1 == operand2) == (op == BinaryOp::Eq),
+            ));
+        }
+    };
+    if settings.strict || op.is_bitwise() {
+        type_check_op(
+            Literal::Num(operand1.clone()),
+            op.lexeme().to_string(),
+            Literal::Num(operand2.clone()),
+        )?;
+    }
+    if op.is_comparison() {
+        return Ok(Literal::Bool(operand1.compare(op, &operand2)?));
+    }
+    let result = match op {
+        BinaryOp::Add => operand1 + operand2,
+        BinaryOp::Sub => operand1 - operand2,
+        BinaryOp::Mul => operand1 * operand2,
+        BinaryOp::Div => match settings.decimal {
+            Some(scale) => operand1.div_decimal(operand2, scale, settings.rounding),
+            None if settings.exact => operand1.div_exact(operand2),
+            None => operand1 / operand2,
+        },
+        BinaryOp::FloorDiv => operand1.floor_div(operand2),
+        BinaryOp::Mod => operand1.floor_mod(operand2),
+        BinaryOp::Pow if settings.exact => operand1.pow_exact(operand2),
+        BinaryOp::Pow => operand1.pow(operand2),
+        BinaryOp::BitAnd => operand1 & operand2,
+        BinaryOp::BitOr => operand1 | operand2,
+        BinaryOp::Xor => operand1 ^ operand2,
+        BinaryOp::Shl => operand1 << operand2,
+        BinaryOp::Shr => operand1 >> operand2,
+        _ => unreachable!(),
+    }?;
+    // In decimal mode, exact results are shown as decimals too.
+    match (result, settings.decimal) {
+        (Number::Decimal(num), Some(scale)) => Ok(Literal::Num(Number::Decimal(
+            num.round(scale, settings.rounding),
+        ))),
+        (Number::Rational(num), Some(scale)) => Ok(Literal::Num(Number::Decimal(
+            Decimal::from_rational(&num, scale, settings.rounding).trim(),
+        ))),
+        (result, _) => Ok(Literal::Num(result)),
     }
 }
 
 mod test {
+    use crate::error::ParserError;
     use crate::eval_expr;
-    use std::collections::HashMap;
+    use crate::math::{division_by_zero, take_int, Literal, Number};
+    use crate::session::Session;
+    use num_bigint::BigInt;
+    use num_complex::Complex64;
+    use num_rational::BigRational;
     #[test]
     fn ops() {
-        let mut var = HashMap::new();
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("1 + 2 * 3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(1 + 2 * 3))))
+        );
+        assert_eq!(
+            eval_expr("(1 + 2) * 3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32((1 + 2) * 3))))
+        );
+        assert_eq!(
+            eval_expr(
+                "(12 + (2 - 3)) * ( 5 +(3 / 8)) + 3".to_string(),
+                &mut session
+            ),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::F64((12. + (2. - 3.)) * (5. + (3. / 8.)) + 3.))
+            ))
+        );
+    }
+    #[test]
+    fn unary() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("-3 * 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(-6))))
+        );
+        assert_eq!(
+            eval_expr("-(1 + 2)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(-3))))
+        );
+        assert_eq!(
+            eval_expr("2 * -3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(-6))))
+        );
+        assert_eq!(
+            eval_expr("-2 ^ 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(-4))))
+        );
+        assert_eq!(
+            eval_expr("2 ^ -1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(0.5))))
+        );
+        assert_eq!(
+            eval_expr("1 - - + 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_expr("-1.5".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(-1.5))))
+        );
+    }
+    #[test]
+    fn floored_division() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("7 // 2", Number::I32(3)),
+            ("-7 // 2", Number::I32(-4)),
+            ("7 // -2", Number::I32(-4)),
+            ("-7 // -2", Number::I32(3)),
+            ("7 % 3", Number::I32(1)),
+            ("-7 % 2", Number::I32(1)),
+            ("7 % -2", Number::I32(-1)),
+            ("-7 % -2", Number::I32(-1)),
+            ("1 + 10 % 4 * 2", Number::I32(5)),
+            ("7.5 // 2.0", Number::F64(3.)),
+            ("-7.5 % 2.0", Number::F64(0.5)),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                expr
+            );
+        }
+        assert!(eval_expr("1 // 0".to_string(), &mut session).is_err());
+        assert!(eval_expr("1 % 0".to_string(), &mut session).is_err());
+        assert!(eval_expr("1.0 % 0.0".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn arithmetic() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("7 / 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(3.5))))
+        );
+        assert_eq!(
+            eval_expr("1 / 0".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Division by zero".to_string()
+            ))
+        );
+        assert_eq!(Number::I32(-1) + Number::U32(3), Ok(Number::I32(2)));
+        assert_eq!(Number::U32(3) - Number::I32(5), Ok(Number::I32(-2)));
+        assert_eq!(Number::U32(3) * Number::U32(5), Ok(Number::U32(15)));
+        assert_eq!(Number::I32(1) + Number::F64(0.5), Ok(Number::F64(1.5)));
+        assert_eq!(-Number::U32(3), Ok(Number::I32(-3)));
+    }
+    #[test]
+    fn wide_integers() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("2147483647 + 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I64(2147483648))))
+        );
+        assert_eq!(
+            eval_expr("65536 * 65536".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I64(1 << 32))))
+        );
+        assert_eq!(
+            eval_expr("-2147483648".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I64(-2147483648))))
+        );
+        assert_eq!(
+            eval_expr("9223372036854775807 - 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I64(i64::MAX - 1))))
+        );
+        assert_eq!(
+            eval_expr("2 ^ 100".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::BigInt(BigInt::from(1) << 100))
+            ))
+        );
+        assert_eq!(
+            eval_expr("100000000000000000000 // 3 % 7".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::BigInt(BigInt::from(
+                    100000000000000000000u128 / 3 % 7
+                )))
+            ))
+        );
+        assert_eq!(
+            eval_expr("10 ^ 10 ^ 10".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Overflow while computing 10 ^ 10000000000".to_string()
+            ))
+        );
+        // Bases whose powers don't grow take any exponent.
+        let cases = vec![
+            ("1 ^ 4000000000", "1"),
+            ("(-1) ^ 10000001", "-1"),
+            ("(-1) ^ 100000000000000000000", "1"),
+            ("0 ^ 4000000000", "0"),
+            ("0 ^ 0", "1"),
+        ];
+        for (expr, result) in cases {
+            let (_, num) = eval_expr(expr.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", num), result, "{}", expr);
+        }
+        assert_eq!(Number::U32(1) - Number::U32(2), Ok(Number::I32(-1)));
+        assert_eq!(
+            Number::U32(u32::MAX) + Number::U32(1),
+            Ok(Number::U64(1 << 32))
+        );
+        assert_eq!(
+            Number::U32(u32::MAX) + Number::I32(0),
+            Ok(Number::I64(u32::MAX as i64))
+        );
+        assert_eq!(
+            Number::U64(u64::MAX) * Number::U32(2),
+            Ok(Number::BigInt(BigInt::from(u64::MAX) * 2))
+        );
+        assert_eq!(-Number::U64(5), Ok(Number::I64(-5)));
+    }
+    #[test]
+    fn display() {
+        let mut session = Session::new();
+        let (_, result) = eval_expr("0.1 + 0.2".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", result), "0.3");
+        assert_eq!(format!("{:.17}", result), "0.30000000000000004");
+        assert_eq!(format!("{:.3}", Number::F64(2. / 3.)), "0.667");
+        assert_eq!(format!("{:.2}", Number::F64(9.99)), "10.0");
+        assert_eq!(format!("{:.3}", Number::F64(-1234.5)), "-1230.0");
+        assert_eq!(format!("{}", Number::F64(2.)), "2.0");
+        assert_eq!(format!("{:.3}", Number::I64(123456789)), "123456789");
+        assert_eq!(format!("{}", Number::F64(f64::INFINITY)), "inf");
+    }
+    #[test]
+    fn rationals() {
+        let mut session = Session::new();
+        session.settings.exact = true;
+        let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+        assert_eq!(
+            eval_expr("1/3 + 1/6".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::Rational(half.clone()))))
+        );
+        assert_eq!(
+            eval_expr("2/3 * 3/2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(1))))
+        );
+        assert_eq!(
+            eval_expr("2 ^ -1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::Rational(half.clone()))))
+        );
+        assert_eq!(
+            eval_expr("(1/2) ^ 2 - 1/4".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(0))))
+        );
+        assert_eq!(
+            eval_expr("7/2 // 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(
+            eval_expr("-7/2 % 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::Rational(half.clone()))))
+        );
+        assert_eq!(
+            eval_expr("1/2 + 0.25".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(0.75))))
+        );
+        assert_eq!(
+            eval_expr("float(1/3)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(1. / 3.))))
+        );
+        assert!(eval_expr("1/0".to_string(), &mut session).is_err());
+        assert!(eval_expr("0 ^ -1".to_string(), &mut session).is_err());
+        assert_eq!(format!("{}", Number::Rational(-half)), "-1/2");
+        session.settings.exact = false;
+        assert_eq!(
+            eval_expr("1/4".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(0.25))))
+        );
+    }
+    #[test]
+    fn complex() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("3 + 4i".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(3., 4.)))
+            ))
+        );
+        assert_eq!(
+            eval_expr("(1 + 2i) * (3 - 1i)".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(5., 5.)))
+            ))
+        );
+        assert_eq!(
+            eval_expr("(5 + 5i) / (1 + 2i)".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(3., -1.)))
+            ))
+        );
+        assert_eq!(
+            eval_expr("sqrt(-4)".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(0., 2.)))
+            ))
+        );
+        assert_eq!(
+            eval_expr("2.5i - 1/2".to_string(), &mut session),
+            Ok((
+                "".to_string(),
+                Literal::Num(Number::Complex(Complex64::new(-0.5, 2.5)))
+            ))
+        );
+        assert!(eval_expr("1i // 2".to_string(), &mut session).is_err());
+        assert!(eval_expr("1 / (0i)".to_string(), &mut session).is_err());
+        let cases = vec![
+            ("0i ^ 0", Complex64::new(1., 0.)),
+            ("0i ^ 0i", Complex64::new(1., 0.)),
+            ("0 ^ 0i", Complex64::new(1., 0.)),
+            ("0i ^ 2", Complex64::new(0., 0.)),
+            ("0i ^ (0.5 + 1i)", Complex64::new(0., 0.)),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(Number::Complex(result)))),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            eval_expr("0i ^ -1".to_string(), &mut session),
+            Err(division_by_zero())
+        );
+        assert!(eval_expr("0i ^ (-2 + 1i)".to_string(), &mut session).is_err());
+        assert_eq!(
+            eval_expr("2in".to_string(), &mut session),
+            Ok(("in".to_string(), Literal::Num(Number::I32(2))))
+        );
+        session.settings.strict = true;
+        assert!(eval_expr("1 + 1.5i".to_string(), &mut session).is_ok());
+        assert_eq!(
+            eval_expr("1 + 1.5".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Cannot apply operator \"+\" between 1 and 1.5".to_string()
+            ))
+        );
+        assert_eq!(
+            format!("{}", Number::Complex(Complex64::new(3., -4.))),
+            "3-4i"
+        );
+        assert_eq!(
+            format!("{:.3}", Number::Complex(Complex64::new(0., 2. / 3.))),
+            "0.667i"
+        );
+    }
+    #[test]
+    fn decimals() {
+        let mut session = Session::new();
+        assert_eq!(session.run_command(":decimal 2".to_string()), Ok(()));
+        let cases = vec![
+            ("0.1 + 0.2", "0.3"),
+            ("1.50 + 1", "2.50"),
+            ("10 / 4", "2.5"),
+            ("1 / 3", "0.33"),
+            ("2 / 3", "0.67"),
+            ("0.125 * 1", "0.12"),
+            ("0.135 * 1", "0.14"),
+            ("1.05 ^ 2", "1.10"),
+            ("-7.5 % 2", "0.5"),
+            ("7.25 // 2", "3"),
+            ("0.5 + 1/2", "1.0"),
+        ];
+        for (expr, result) in cases.clone() {
+            let (_, num) = eval_expr(expr.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", num), result, "{}", expr);
+        }
+        assert_eq!(session.run_command(":rounding half-up".to_string()), Ok(()));
+        let (_, num) = eval_expr("0.125 * 1".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", num), "0.13");
+        assert_eq!(
+            session.run_command(":rounding truncate".to_string()),
+            Ok(())
+        );
+        let (_, num) = eval_expr("2 / 3".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", num), "0.66");
+        assert!(session
+            .run_command(":rounding nearest".to_string())
+            .is_err());
+        // The scale of a power is bounded like its mantissa.
+        assert_eq!(session.run_command(":decimal 10000".to_string()), Ok(()));
+        assert!(eval_expr("1e-5000 ^ 1000000".to_string(), &mut session).is_err());
+        assert!(eval_expr("1e-5000 ^ 800000".to_string(), &mut session).is_err());
+        // Exact results are shown as decimals too.
+        assert_eq!(session.run_command(":decimal 2".to_string()), Ok(()));
+        assert_eq!(session.run_command(":exact on".to_string()), Ok(()));
+        let cases = vec![
+            ("2 ^ -1", "0.5"),
+            ("(2 ^ -2) + 0.1", "0.35"),
+            ("1 / 3", "0.33"),
+        ];
+        for (expr, result) in cases {
+            let (_, num) = eval_expr(expr.to_string(), &mut session).unwrap();
+            assert!(matches!(num, Literal::Num(Number::Decimal(_))), "{}", expr);
+            assert_eq!(format!("{}", num), result, "{}", expr);
+        }
+        assert_eq!(session.run_command(":exact off".to_string()), Ok(()));
+        assert_eq!(session.run_command(":decimal off".to_string()), Ok(()));
+        assert_eq!(
+            eval_expr("0.5 + 0.25".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(0.75))))
+        );
+    }
+    #[test]
+    fn int_literals() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("0x1F", Number::I32(31)),
+            ("0xff + 1", Number::I32(256)),
+            ("0o17", Number::I32(15)),
+            ("0b1010", Number::I32(10)),
+            ("1_000_000", Number::I32(1000000)),
+            ("0b1111_0000", Number::I32(240)),
+            ("0xFFFF_FFFF", Number::I64(u32::MAX as i64)),
+            ("1_000.000_5", Number::F64(1000.0005)),
+            ("0x10i", Number::Complex(Complex64::new(0., 16.))),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            eval_expr("1_".to_string(), &mut session),
+            Ok(("_".to_string(), Literal::Num(Number::I32(1))))
+        );
+        assert!(take_int("0b102".to_string()).is_err());
+        assert!(take_int("0xFG".to_string()).is_err());
+        assert!(take_int("0x".to_string()).is_err());
+    }
+    #[test]
+    fn scientific() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("1e9", Number::F64(1e9)),
+            ("6.02e23", Number::F64(6.02e23)),
+            ("1.5E-3", Number::F64(1.5e-3)),
+            ("2e+2 + 1", Number::F64(201.)),
+            (".5", Number::F64(0.5)),
+            ("-.25e1", Number::F64(-2.5)),
+            ("1e400", Number::F64(f64::INFINITY)),
+            ("2e3i", Number::Complex(Complex64::new(0., 2000.))),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            eval_expr("1e".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Malformed exponent in \"1e\": expected digits, found nothing".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_expr("2 * 1.5e-x".to_string(), &mut session),
+            Err(ParserError::newr(
+                "x".to_string(),
+                "Malformed exponent in \"1.5e-\": expected digits, found 'x'".to_string()
+            ))
+        );
+        assert!(eval_expr("1e99999".to_string(), &mut session).is_err());
+        assert_eq!(format!("{}", Number::F64(6.02e23)), "6.02e23");
+        assert_eq!(format!("{}", Number::F64(-1.5e-7)), "-1.5e-7");
+        assert_eq!(format!("{}", Number::F64(1e16)), "1e16");
+        assert_eq!(format!("{:.3}", Number::F64(1.23456e-9)), "1.23e-9");
+        assert_eq!(
+            format!("{}", Number::F64(123456789012345.)),
+            "123456789012345.0"
+        );
+        assert_eq!(format!("{}", Number::F64(0.00001)), "0.00001");
+        session.settings.decimal = Some(4);
+        let (_, num) = eval_expr("1.5e-3 + 2e2".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", num), "200.0015");
+    }
+    #[test]
+    fn bitwise() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("6 & 3", Number::I32(2)),
+            ("6 | 3", Number::I32(7)),
+            ("6 xor 3", Number::I32(5)),
+            ("~5", Number::I32(-6)),
+            ("~0x0F & 0xFF", Number::I32(0xF0)),
+            ("-1 & 0xFF", Number::I32(0xFF)),
+            ("1 << 4", Number::I32(16)),
+            ("1 << 40", Number::I64(1 << 40)),
+            ("-16 >> 2", Number::I32(-4)),
+            ("-1 >> 100", Number::I32(-1)),
+            ("1 + 2 << 3", Number::I32(24)),
+            ("1 << 2 | 1", Number::I32(5)),
+            ("1 | 2 & 3", Number::I32(3)),
+            ("6 & 3 xor 1", Number::I32(3)),
+            ("1 xor 2 | 4", Number::I32(7)),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(!Number::U32(0), Ok(Number::U32(u32::MAX)));
+        assert_eq!(
+            eval_expr("1.5 & 1".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Operator \"&\" only applies to integers, found 1.5".to_string()
+            ))
+        );
+        assert!(eval_expr("~1.5".to_string(), &mut session).is_err());
+        assert!(eval_expr("(1/2) | 1".to_string(), &mut session).is_err());
+        assert!(eval_expr("1 << -1".to_string(), &mut session).is_err());
+        assert!(eval_expr("1 << 10000000".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn booleans() {
+        let mut session = Session::new();
+        let cases = vec![
+            ("1 < 2", true),
+            ("2 <= 2", true),
+            ("3 > 4", false),
+            ("3 >= 4", false),
+            ("2 != 3", true),
+            ("1 == 1.0", true),
+            ("1/2 == 0.5", true),
+            ("0.1 + 0.2 == 0.3", false),
+            ("2^100 > 2^99", true),
+            ("1i == 1i", true),
+            ("6 & 1 == 0", true),
+            ("1 + 2 == 3 and 2 > 1", true),
+            ("1 > 2 or 2 > 1", true),
+            ("not 1 == 2", true),
+            ("not true or true", true),
+            ("true == false", false),
+            ("true != false", true),
+            ("true or 1 / 0 == 1", true),
+            ("false and 1 / 0 == 1", false),
+        ];
+        for (expr, result) in cases {
+            assert_eq!(
+                eval_expr(expr.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Bool(result))),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            eval_expr("true + 1".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Cannot apply operator \"+\" between true and 1".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_expr("not 1".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Operator \"not\" expects a boolean, found 1".to_string()
+            ))
+        );
+        assert!(eval_expr("1 and true".to_string(), &mut session).is_err());
+        assert!(eval_expr("true and 1".to_string(), &mut session).is_err());
+        assert!(eval_expr("true < false".to_string(), &mut session).is_err());
+        assert!(eval_expr("1i < 2".to_string(), &mut session).is_err());
+        assert!(eval_expr("-true".to_string(), &mut session).is_err());
+        assert!(eval_expr("sqrt(true)".to_string(), &mut session).is_err());
+        session.settings.decimal = Some(2);
+        assert_eq!(
+            eval_expr("0.1 + 0.2 == 0.3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Bool(true)))
+        );
+        assert_eq!(format!("{}", Literal::Bool(false)), "false");
+    }
+    #[test]
+    fn conditionals() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("if 1 < 2 then 10 else 1 / 0".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(10))))
+        );
+        assert_eq!(
+            eval_expr("if false then 1 / 0 else 2 * 3".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(6))))
+        );
+        assert_eq!(
+            eval_expr("1 + (if true then 1 else 2) * 10".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(11))))
+        );
+        let tax = "if income <= 10000 then 0.0 \
+                   else if income <= 40000 then (income - 10000) * 0.1 \
+                   else 3000 + (income - 40000) * 0.2";
+        for (income, result) in [(5000, 0.), (20000, 1000.), (50000, 5000.)] {
+            let mut session = Session::new();
+            session
+                .variables
+                .insert("income".to_string(), Literal::Num(Number::I32(income)));
+            let (_, num) = eval_expr(tax.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", num), format!("{}", Number::F64(result)));
+        }
+        assert_eq!(
+            eval_expr("if 1 then 2 else 3".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "\"if\" expects a boolean, found 1".to_string()
+            ))
+        );
+        assert!(eval_expr("if true then 1".to_string(), &mut session).is_err());
+        assert!(eval_expr("if true 1 else 2".to_string(), &mut session).is_err());
+    }
+    #[test]
+    fn pow() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_expr("2 ^ 3 ^ 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(512))))
+        );
+        assert_eq!(
+            eval_expr("2 ** 3 ** 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(512))))
+        );
+        assert_eq!(
+            eval_expr("3 * 2 ^ 2 + 1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(13))))
+        );
+        assert_eq!(
+            eval_expr("2 ^ (1 - 2)".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(0.5))))
+        );
         assert_eq!(
-            eval_expr("1 + 2 * 3".to_string(), &mut var),
-            Ok(("".to_string(), 1 + 2 * 3))
+            eval_expr("2.0 ^ 0.5".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(2f64.sqrt()))))
         );
+        // A negative power of an integer zero is a division by zero, as in exact
+        // mode, while float zeros follow IEEE 754.
         assert_eq!(
-            eval_expr("(1 + 2) * 3".to_string(), &mut var),
-            Ok(("".to_string(), (1 + 2) * 3))
+            eval_expr("0 ^ -1".to_string(), &mut session),
+            Err(division_by_zero())
         );
         assert_eq!(
-            eval_expr("(12 + (2 - 3)) * ( 5 +(3 / 8)) + 3".to_string(), &mut var),
-            Ok(("".to_string(), (12 + (2 - 3)) * (5 + (3 / 8)) + 3))
+            eval_expr("0.0 ^ -1".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(f64::INFINITY))))
         );
     }
 }
@@ -0,0 +1,429 @@
+use crate::{
+    error::ParserError,
+    math::{eval_binary, expect_number, BinaryOp, Literal, Number},
+    session::Settings,
+};
+
+/// Dimensions of a list: `[1, 2, 3]` is `[3]` and `[[1, 2, 3], [4, 5, 6]]` is
+/// `[2, 3]`. The rows of a matrix must all have the same shape.
+pub fn shape(items: &[Literal]) -> Result<Vec<usize>, ParserError> {
+    let mut shape = vec![items.len()];
+    let inner = match items.first() {
+        Some(Literal::List(first)) => Some(self::shape(first)?),
+        _ => None,
+    };
+    for item in items {
+        let same = match (item, &inner) {
+            (Literal::List(row), Some(inner)) => &self::shape(row)? == inner,
+            (Literal::List(_), None) | (_, Some(_)) => false,
+            (_, None) => true,
+        };
+        if !same {
+            return Err(ParserError::newr(
+                "".to_string(),
+                format!(
+                    "The rows of {} don't have the same shape",
+                    Literal::List(items.to_vec())
+                ),
+            ));
+        }
+    }
+    shape.extend(inner.unwrap_or_default());
+    Ok(shape)
+}
+/// `2x3` for a matrix of 2 rows and 3 columns, `3` for a vector.
+fn format_shape(shape: &[usize]) -> String {
+    shape
+        .iter()
+        .map(|dim| dim.to_string())
+        .collect::<Vec<_>>()
+        .join("x")
+}
+fn dimension_mismatch(op: BinaryOp, shape1: &[usize], shape2: &[usize]) -> ParserError {
+    ParserError::newr(
+        "".to_string(),
+        format!(
+            "Dimension mismatch for operator {:#?}: {} and {}",
+            op.lexeme(),
+            format_shape(shape1),
+            format_shape(shape2)
+        ),
+    )
+}
+/// Operators with at least one list operand: `+` and `-` are element-wise, a list
+/// can be multiplied or divided by a number, and `*` between two lists is the
+/// matrix product, a vector being a row on the left and a column on the right.
+pub fn eval_list_op(
+    op: BinaryOp,
+    operand1: Literal,
+    operand2: Literal,
+    settings: &Settings,
+) -> Result<Literal, ParserError> {
+    match (op, operand1, operand2) {
+        (BinaryOp::Add | BinaryOp::Sub, Literal::List(items1), Literal::List(items2)) => {
+            let (shape1, shape2) = (shape(&items1)?, shape(&items2)?);
+            if shape1 != shape2 {
+                return Err(dimension_mismatch(op, &shape1, &shape2));
+            }
+            items1
+                .into_iter()
+                .zip(items2)
+                .map(|(item1, item2)| eval_binary(op, item1, item2, settings))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Literal::List)
+        }
+        (BinaryOp::Mul, Literal::List(items1), Literal::List(items2)) => {
+            matmul(items1, items2, settings)
+        }
+        (BinaryOp::Mul, scalar @ Literal::Num(_), Literal::List(items)) => items
+            .into_iter()
+            .map(|item| eval_binary(op, scalar.clone(), item, settings))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Literal::List),
+        (BinaryOp::Mul | BinaryOp::Div, Literal::List(items), scalar @ Literal::Num(_)) => items
+            .into_iter()
+            .map(|item| eval_binary(op, item, scalar.clone(), settings))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Literal::List),
+        (op, operand1, operand2) => Err(ParserError::newr(
+            "".to_string(),
+            format!(
+                "Cannot apply operator {:#?} between {} and {}",
+                op.lexeme(),
+                operand1,
+                operand2
+            ),
+        )),
+    }
+}
+/// Sum of the products of the items of `row` and `column`, which have the same
+/// length.
+fn dot_product(
+    row: &[Literal],
+    column: &[Literal],
+    settings: &Settings,
+) -> Result<Literal, ParserError> {
+    let mut total: Option<Literal> = None;
+    for (item1, item2) in row.iter().zip(column) {
+        let product = eval_binary(BinaryOp::Mul, item1.clone(), item2.clone(), settings)?;
+        total = Some(match total {
+            Some(total) => eval_binary(BinaryOp::Add, total, product, settings)?,
+            None => product,
+        });
+    }
+    Ok(total.unwrap_or(Literal::Num(Number::I32(0))))
+}
+/// `dot(u, v)`, the dot product of two vectors of the same length.
+pub fn dot(
+    items1: Vec<Literal>,
+    items2: Vec<Literal>,
+    settings: &Settings,
+) -> Result<Literal, ParserError> {
+    let (shape1, shape2) = (shape(&items1)?, shape(&items2)?);
+    if shape1.len() != 1 || shape1 != shape2 {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!(
+                "dot expects two vectors of the same length, found shapes {} and {}",
+                format_shape(&shape1),
+                format_shape(&shape2)
+            ),
+        ));
+    }
+    dot_product(&items1, &items2, settings)
+}
+fn matmul(
+    items1: Vec<Literal>,
+    items2: Vec<Literal>,
+    settings: &Settings,
+) -> Result<Literal, ParserError> {
+    let (shape1, shape2) = (shape(&items1)?, shape(&items2)?);
+    let inner1 = shape1.last().copied().unwrap_or(0);
+    if shape1.len() > 2 || shape2.len() > 2 || inner1 != shape2[0] {
+        return Err(dimension_mismatch(BinaryOp::Mul, &shape1, &shape2));
+    }
+    let rows = match shape1.len() {
+        1 => vec![items1],
+        _ => to_rows(items1),
+    };
+    let columns = match shape2.len() {
+        1 => vec![items2],
+        _ => to_rows(transpose(items2)?),
+    };
+    let mut product = vec![];
+    for row in &rows {
+        let mut items = vec![];
+        for column in &columns {
+            items.push(dot_product(row, column, settings)?);
+        }
+        product.push(match shape2.len() {
+            1 => items.pop().unwrap(),
+            _ => Literal::List(items),
+        });
+    }
+    match shape1.len() {
+        1 => Ok(product.pop().unwrap()),
+        _ => Ok(Literal::List(product)),
+    }
+}
+fn to_rows(items: Vec<Literal>) -> Vec<Vec<Literal>> {
+    items
+        .into_iter()
+        .map(|row| match row {
+            Literal::List(row) => row,
+            row => vec![row],
+        })
+        .collect()
+}
+/// Swaps the rows and columns of a matrix. A vector is a single row, so it becomes
+/// a column.
+pub fn transpose(items: Vec<Literal>) -> Result<Vec<Literal>, ParserError> {
+    let shape = shape(&items)?;
+    if shape.len() > 2 {
+        return Err(not_a_matrix("transpose", &shape));
+    }
+    if shape.len() == 1 {
+        return Ok(items
+            .into_iter()
+            .map(|item| Literal::List(vec![item]))
+            .collect());
+    }
+    let rows = to_rows(items);
+    Ok((0..shape[1])
+        .map(|j| Literal::List(rows.iter().map(|row| row[j].clone()).collect()))
+        .collect())
+}
+/// The rows of a square matrix of numbers.
+fn square_matrix(name: &str, items: Vec<Literal>) -> Result<Vec<Vec<Number>>, ParserError> {
+    let shape = shape(&items)?;
+    if shape.len() != 2 || shape[0] != shape[1] {
+        return Err(not_a_matrix(name, &shape));
+    }
+    let context = format!("Function {:#?}", name);
+    to_rows(items)
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|item| expect_number(item, &context))
+                .collect()
+        })
+        .collect()
+}
+fn not_a_matrix(name: &str, shape: &[usize]) -> ParserError {
+    let expected = if name == "transpose" { "a" } else { "a square" };
+    ParserError::newr(
+        "".to_string(),
+        format!(
+            "{} expects {} matrix, found shape {}",
+            name,
+            expected,
+            format_shape(shape)
+        ),
+    )
+}
+fn num_op(
+    op: BinaryOp,
+    num1: Number,
+    num2: Number,
+    settings: &Settings,
+) -> Result<Number, ParserError> {
+    let context = format!("Operator {:#?}", op.lexeme());
+    expect_number(
+        eval_binary(op, Literal::Num(num1), Literal::Num(num2), settings)?,
+        &context,
+    )
+}
+/// Index of the row at or below `col` with the largest pivot, `None` if the column
+/// is zero from there on.
+fn pivot_row(rows: &[Vec<Number>], col: usize) -> Option<usize> {
+    (col..rows.len())
+        .filter(|&i| !rows[i][col].is_zero())
+        .max_by(|&i, &j| {
+            let (a, b) = (rows[i][col].magnitude(), rows[j][col].magnitude());
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
+/// Determinant with the Bareiss algorithm: its divisions are exact, so the
+/// determinant of an integer matrix is an integer.
+pub fn det(items: Vec<Literal>, settings: &Settings) -> Result<Literal, ParserError> {
+    let mut rows = square_matrix("det", items)?;
+    let n = rows.len();
+    let mut sign = Number::I32(1);
+    let mut previous = Number::I32(1);
+    for k in 0..n {
+        let pivot = match pivot_row(&rows, k) {
+            Some(pivot) => pivot,
+            None => return Ok(Literal::Num(Number::I32(0))),
+        };
+        if pivot != k {
+            rows.swap(pivot, k);
+            sign = (-sign)?;
+        }
+        for i in k + 1..n {
+            for j in k + 1..n {
+                let a = num_op(
+                    BinaryOp::Mul,
+                    rows[i][j].clone(),
+                    rows[k][k].clone(),
+                    settings,
+                )?;
+                let b = num_op(
+                    BinaryOp::Mul,
+                    rows[i][k].clone(),
+                    rows[k][j].clone(),
+                    settings,
+                )?;
+                let difference = num_op(BinaryOp::Sub, a, b, settings)?;
+                rows[i][j] = difference.div_exact(previous.clone())?;
+            }
+        }
+        previous = rows[k][k].clone();
+    }
+    match n {
+        0 => Ok(Literal::Num(Number::I32(1))),
+        _ => num_op(BinaryOp::Mul, sign, rows[n - 1][n - 1].clone(), settings).map(Literal::Num),
+    }
+}
+/// Inverse with the Gauss-Jordan elimination. Divisions follow the mode, so the
+/// inverse is exact in exact mode.
+pub fn inverse(items: Vec<Literal>, settings: &Settings) -> Result<Literal, ParserError> {
+    let mut rows = square_matrix("inv", items)?;
+    let n = rows.len();
+    let mut inverse: Vec<Vec<Number>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| Number::I32(if i == j { 1 } else { 0 }))
+                .collect()
+        })
+        .collect();
+    for k in 0..n {
+        let pivot = pivot_row(&rows, k).ok_or_else(|| {
+            ParserError::newr("".to_string(), "inv of a singular matrix".to_string())
+        })?;
+        rows.swap(pivot, k);
+        inverse.swap(pivot, k);
+        let pivot = rows[k][k].clone();
+        for j in 0..n {
+            rows[k][j] = num_op(BinaryOp::Div, rows[k][j].clone(), pivot.clone(), settings)?;
+            inverse[k][j] = num_op(
+                BinaryOp::Div,
+                inverse[k][j].clone(),
+                pivot.clone(),
+                settings,
+            )?;
+        }
+        for i in (0..n).filter(|&i| i != k) {
+            let factor = rows[i][k].clone();
+            if factor.is_zero() {
+                continue;
+            }
+            for j in 0..n {
+                let a = num_op(BinaryOp::Mul, factor.clone(), rows[k][j].clone(), settings)?;
+                rows[i][j] = num_op(BinaryOp::Sub, rows[i][j].clone(), a, settings)?;
+                let b = num_op(
+                    BinaryOp::Mul,
+                    factor.clone(),
+                    inverse[k][j].clone(),
+                    settings,
+                )?;
+                inverse[i][j] = num_op(BinaryOp::Sub, inverse[i][j].clone(), b, settings)?;
+            }
+        }
+    }
+    Ok(Literal::List(
+        inverse
+            .into_iter()
+            .map(|row| Literal::List(row.into_iter().map(Literal::Num).collect()))
+            .collect(),
+    ))
+}
+
+mod test {
+    use crate::error::ParserError;
+    use crate::eval_line;
+    use crate::session::Session;
+
+    #[test]
+    fn matrices() {
+        let mut session = Session::new();
+        eval_line("let m = [[1, 2], [3, 4]];".to_string(), &mut session).unwrap();
+        let cases = vec![
+            ("[1, 2, 3] + [10, 20, 30]", "[11, 22, 33]"),
+            ("m - [[1, 1], [1, 1]]", "[[0, 1], [2, 3]]"),
+            ("2 * m", "[[2, 4], [6, 8]]"),
+            ("[1, 2] * 1.5", "[1.5, 3.0]"),
+            ("m / 2", "[[0.5, 1.0], [1.5, 2.0]]"),
+            ("dot([1, 2, 3], [4, 5, 6])", "32"),
+            ("[1, 2, 3] * [4, 5, 6]", "32"),
+            ("m * m", "[[7, 10], [15, 22]]"),
+            ("m * [1, 1]", "[3, 7]"),
+            ("[1, 1] * m", "[4, 6]"),
+            ("[[1, 2, 3]] * [[1], [2], [3]]", "[[14]]"),
+            (
+                "transpose([[1, 2, 3], [4, 5, 6]])",
+                "[[1, 4], [2, 5], [3, 6]]",
+            ),
+            ("transpose([1, 2])", "[[1], [2]]"),
+            ("det(m)", "-2"),
+            ("det([[2, 0, 1], [1, 3, 2], [1, 1, 2]])", "6"),
+            ("det([[0, 1], [1, 0]])", "-1"),
+            ("det([[1, 2], [2, 4]])", "0"),
+            ("det([[0.5]])", "0.5"),
+            ("inv([[2, 0], [0, 4]])", "[[0.5, 0.0], [0.0, 0.25]]"),
+            ("inv(m)", "[[-2.0, 1.0], [1.5, -0.5]]"),
+        ];
+        for (line, result) in cases {
+            let (_, value) = eval_line(line.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", value), result, "{}", line);
+        }
+        // The elimination's own arithmetic isn't subject to strict mode.
+        session.run_command(":strict on".to_string()).unwrap();
+        let cases = vec![
+            ("inv([[1, 2], [3, 4]])", "[[-2.0, 1.0], [1.5, -0.5]]"),
+            ("det([[1, 2], [3, 4]])", "-2"),
+            ("[1.5, 2.0] * [2.0, 4.0]", "11.0"),
+            ("[[1.5]] * [[2.0]]", "[[3.0]]"),
+        ];
+        for (line, result) in cases {
+            let (_, value) = eval_line(line.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{}", value), result, "{}", line);
+        }
+        session.run_command(":strict off".to_string()).unwrap();
+        session.run_command(":exact on".to_string()).unwrap();
+        let (_, value) = eval_line("inv(m)".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", value), "[[-2, 1], [3/2, -1/2]]");
+        assert_eq!(
+            eval_line("[1, 2] + [1, 2, 3]".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Dimension mismatch for operator \"+\": 2 and 3".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("m * [[1, 2, 3]]".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "Dimension mismatch for operator \"*\": 2x2 and 1x3".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("det([[1, 2, 3]])".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "det expects a square matrix, found shape 1x3".to_string()
+            ))
+        );
+        assert!(eval_line("inv([[1, 2], [2, 4]])".to_string(), &mut session).is_err());
+        session.run_command(":decimal 4".to_string()).unwrap();
+        let (_, value) =
+            eval_line("det([[0.1, 0.2], [0.3, 0.7]])".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", value), "0.01");
+        let (_, value) =
+            eval_line("det([[0.5, 0.25], [0.1, 1]])".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", value), "0.475");
+        assert!(eval_line("[[1, 2], [3]] + m".to_string(), &mut session).is_err());
+        assert!(eval_line("[1, 2] + 1".to_string(), &mut session).is_err());
+        assert!(eval_line("1 / [1, 2]".to_string(), &mut session).is_err());
+        assert!(eval_line("det([[true]])".to_string(), &mut session).is_err());
+    }
+}
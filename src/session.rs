@@ -0,0 +1,266 @@
+use crate::{
+    common::{take_char, take_identifier},
+    decimal::Rounding,
+    error::ParserError,
+    math::{Literal, DEFAULT_PRECISION},
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// In strict mode integers and floats can't be mixed in an operation, instead
+    /// of the integer being promoted to a float.
+    pub strict: bool,
+    /// Number of significant digits results are displayed with.
+    pub precision: usize,
+    /// In exact mode dividing integers gives a rational instead of a float.
+    pub exact: bool,
+    /// In decimal mode float literals are decimals, and results are rounded to this
+    /// number of digits after the decimal point. Dividing integers gives a decimal,
+    /// even in exact mode.
+    pub decimal: Option<u32>,
+    pub rounding: Rounding,
+    /// Radix integer results are displayed in, see `Number::to_radix`.
+    pub base: u32,
+    /// Maximum number of nested calls of user functions, so that an infinite
+    /// recursion fails instead of overflowing the stack.
+    pub max_depth: usize,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            precision: DEFAULT_PRECISION,
+            exact: false,
+            decimal: None,
+            rounding: Rounding::HalfEven,
+            base: 10,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+impl Settings {
+    /// The settings of the arithmetic a builtin does on its own intermediate
+    /// values. Strict mode only applies to the operands the user wrote.
+    pub fn lenient(&self) -> Settings {
+        Settings {
+            strict: false,
+            ..self.clone()
+        }
+    }
+}
+/// Everything a line of input can read or change: the declared variables and the
+/// settings changed with `:` commands.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Session {
+    pub variables: HashMap<String, Literal>,
+    pub settings: Settings,
+}
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Runs a command such as `:strict on`.
+    pub fn run_command(&mut self, s: String) -> Result<(), ParserError> {
+        let (remaining, command) =
+            take_char(s.clone(), ':').and_then(|(remaining, _)| take_identifier(remaining))?;
+        match command.as_str() {
+            "strict" => self.settings.strict = take_switch(remaining)?,
+            "precision" => self.settings.precision = take_precision(remaining)?,
+            "exact" => self.settings.exact = take_switch(remaining)?,
+            "decimal" => self.settings.decimal = take_scale(remaining)?,
+            "rounding" => self.settings.rounding = take_rounding(remaining)?,
+            "base" => self.settings.base = take_base(remaining)?,
+            "maxdepth" => self.settings.max_depth = take_max_depth(remaining)?,
+            _ => {
+                return Err(ParserError::newr(
+                    s,
+                    format!("Unknown command: {:#?}", command),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+fn take_switch(s: String) -> Result<bool, ParserError> {
+    match s.trim() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(ParserError::newr(
+            s.clone(),
+            format!("Expected on or off, found {:#?}", s.trim()),
+        )),
+    }
+}
+/// Beyond this many digits after the decimal point, rounding a single division
+/// would already take a noticeable time.
+const MAX_SCALE: u32 = 10_000;
+/// Either `off` or the number of digits after the decimal point.
+fn take_scale(s: String) -> Result<Option<u32>, ParserError> {
+    match s.trim() {
+        "off" => Ok(None),
+        scale => scale
+            .parse::<u32>()
+            .ok()
+            .filter(|scale| *scale <= MAX_SCALE)
+            .map(Some)
+            .ok_or_else(|| {
+                ParserError::newr(
+                    s.clone(),
+                    format!(
+                        "Expected off or a number of decimals up to {}, found {:#?}",
+                        MAX_SCALE, scale
+                    ),
+                )
+            }),
+    }
+}
+fn take_rounding(s: String) -> Result<Rounding, ParserError> {
+    match s.trim() {
+        "half-even" => Ok(Rounding::HalfEven),
+        "half-up" => Ok(Rounding::HalfUp),
+        "truncate" => Ok(Rounding::Truncate),
+        rounding => Err(ParserError::newr(
+            s.clone(),
+            format!(
+                "Expected half-even, half-up or truncate, found {:#?}",
+                rounding
+            ),
+        )),
+    }
+}
+/// A radix between 2 and 36, or one of the names `bin`, `oct`, `dec` and `hex`.
+pub fn take_base(s: String) -> Result<u32, ParserError> {
+    match s.trim() {
+        "bin" => Ok(2),
+        "oct" => Ok(8),
+        "dec" => Ok(10),
+        "hex" => Ok(16),
+        base => base
+            .parse::<u32>()
+            .ok()
+            .filter(|base| (2..=36).contains(base))
+            .ok_or_else(|| {
+                ParserError::newr(
+                    s.clone(),
+                    format!(
+                        "Expected bin, oct, dec, hex or a base between 2 and 36, found {:#?}",
+                        base
+                    ),
+                )
+            }),
+    }
+}
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+/// Deeper recursions could overflow the stack.
+const MAX_MAX_DEPTH: usize = 2000;
+fn take_max_depth(s: String) -> Result<usize, ParserError> {
+    s.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|depth| (1..=MAX_MAX_DEPTH).contains(depth))
+        .ok_or_else(|| {
+            ParserError::newr(
+                s.clone(),
+                format!(
+                    "Expected a depth between 1 and {}, found {:#?}",
+                    MAX_MAX_DEPTH,
+                    s.trim()
+                ),
+            )
+        })
+}
+/// A float has at most 17 significant digits.
+fn take_precision(s: String) -> Result<usize, ParserError> {
+    s.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|digits| (1..=17).contains(digits))
+        .ok_or_else(|| {
+            ParserError::newr(
+                s.clone(),
+                format!(
+                    "Expected a precision between 1 and 17, found {:#?}",
+                    s.trim()
+                ),
+            )
+        })
+}
+
+mod test {
+    use crate::error::ParserError;
+    use crate::eval_line;
+    use crate::math::{Literal, Number};
+    use crate::session::Session;
+
+    #[test]
+    fn strict_mode() {
+        let mut session = Session::new();
+        assert_eq!(
+            eval_line("1 + 2.5".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(3.5))))
+        );
+        assert_eq!(session.run_command(":strict on".to_string()), Ok(()));
+        assert!(eval_line("1 + 2.5".to_string(), &mut session).is_err());
+        assert_eq!(
+            eval_line("1 + 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::I32(3))))
+        );
+        assert_eq!(session.run_command(":strict off".to_string()), Ok(()));
+        assert_eq!(
+            eval_line("2.5 * 2".to_string(), &mut session),
+            Ok(("".to_string(), Literal::Num(Number::F64(5.))))
+        );
+        assert!(session.run_command(":strict maybe".to_string()).is_err());
+        assert!(session.run_command(":unknown".to_string()).is_err());
+    }
+    #[test]
+    fn base() {
+        let mut session = Session::new();
+        assert_eq!(session.run_command(":base 16".to_string()), Ok(()));
+        assert_eq!(session.settings.base, 16);
+        assert_eq!(session.run_command(":base bin".to_string()), Ok(()));
+        assert_eq!(session.settings.base, 2);
+        assert!(session.run_command(":base 1".to_string()).is_err());
+        assert!(session.run_command(":base 37".to_string()).is_err());
+        assert!(session.run_command(":base hexa".to_string()).is_err());
+        assert_eq!(session.settings.base, 2);
+    }
+    #[test]
+    fn max_depth() {
+        let mut session = Session::new();
+        assert_eq!(session.run_command(":maxdepth 50".to_string()), Ok(()));
+        assert_eq!(session.settings.max_depth, 50);
+        assert!(session.run_command(":maxdepth 0".to_string()).is_err());
+        assert!(session.run_command(":maxdepth 100000".to_string()).is_err());
+        assert_eq!(session.settings.max_depth, 50);
+    }
+    #[test]
+    fn decimal() {
+        let mut session = Session::new();
+        assert_eq!(session.run_command(":decimal 10000".to_string()), Ok(()));
+        assert_eq!(session.settings.decimal, Some(10000));
+        assert_eq!(
+            session.run_command(":decimal 2000000000".to_string()),
+            Err(ParserError::newr(
+                "2000000000".to_string(),
+                "Expected off or a number of decimals up to 10000, found \"2000000000\""
+                    .to_string()
+            ))
+        );
+        assert!(session.run_command(":decimal ten".to_string()).is_err());
+        assert_eq!(session.settings.decimal, Some(10000));
+        assert_eq!(session.run_command(":decimal off".to_string()), Ok(()));
+        assert_eq!(session.settings.decimal, None);
+    }
+    #[test]
+    fn precision() {
+        let mut session = Session::new();
+        assert_eq!(session.run_command(":precision 4".to_string()), Ok(()));
+        assert_eq!(session.settings.precision, 4);
+        assert!(session.run_command(":precision 0".to_string()).is_err());
+        assert!(session.run_command(":precision 18".to_string()).is_err());
+        assert!(session.run_command(":precision four".to_string()).is_err());
+        assert_eq!(session.settings.precision, 4);
+    }
+}
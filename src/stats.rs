@@ -0,0 +1,327 @@
+use crate::{
+    error::ParserError,
+    math::{eval_binary, expect_number, BinaryOp, Literal, Number},
+    session::Settings,
+};
+use std::cmp::Ordering;
+
+/// The numbers of the list given to the statistic `name`, which can't be empty.
+/// Nans and complex numbers are rejected, as they can't be ordered.
+pub fn samples(name: &str, items: Vec<Literal>) -> Result<Vec<Number>, ParserError> {
+    if items.is_empty() {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects a non empty list", name),
+        ));
+    }
+    let context = format!("Function {:#?}", name);
+    let samples = items
+        .into_iter()
+        .map(|item| expect_number(item, &context))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(sample) = samples
+        .iter()
+        .find(|num| matches!(num, Number::F64(num) if num.is_nan()))
+    {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects numbers, found {}", name, sample),
+        ));
+    }
+    if let Some(sample) = samples.iter().find(|num| matches!(num, Number::Complex(_))) {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!("{} expects real numbers, found {}", name, sample),
+        ));
+    }
+    Ok(samples)
+}
+fn op(
+    op: BinaryOp,
+    num1: Number,
+    num2: Number,
+    settings: &Settings,
+) -> Result<Number, ParserError> {
+    let context = format!("Operator {:#?}", op.lexeme());
+    expect_number(
+        eval_binary(op, Literal::Num(num1), Literal::Num(num2), settings)?,
+        &context,
+    )
+}
+fn count(samples: &[Number]) -> Number {
+    Number::from_bigint(samples.len().into(), 32, false)
+}
+/// Arithmetic follows the mode, so the mean of integers is exact in exact mode.
+pub fn mean(samples: Vec<Number>, settings: &Settings) -> Result<Number, ParserError> {
+    let count = count(&samples);
+    let mut total = Number::I32(0);
+    for sample in samples {
+        total = op(BinaryOp::Add, total, sample, settings)?;
+    }
+    op(BinaryOp::Div, total, count, settings)
+}
+fn sorted(mut samples: Vec<Number>) -> Vec<Number> {
+    samples.sort_by(|a, b| {
+        if a.compare(BinaryOp::Lt, b).unwrap_or(false) {
+            Ordering::Less
+        } else if a.compare(BinaryOp::Gt, b).unwrap_or(false) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    samples
+}
+/// The middle sample, or the mean of the two middle ones for an even count.
+pub fn median(samples: Vec<Number>, settings: &Settings) -> Result<Number, ParserError> {
+    let mut samples = sorted(samples);
+    let n = samples.len();
+    if n % 2 == 1 {
+        return Ok(samples.swap_remove(n / 2));
+    }
+    let upper = samples.swap_remove(n / 2);
+    let lower = samples.swap_remove(n / 2 - 1);
+    mean(vec![lower, upper], settings)
+}
+/// The most frequent sample, the smallest one in case of a tie.
+pub fn mode(samples: Vec<Number>) -> Result<Number, ParserError> {
+    let samples = sorted(samples);
+    let (mut best, mut best_count) = (0, 0);
+    let mut start = 0;
+    for i in 1..=samples.len() {
+        let same = i < samples.len() && samples[i].compare(BinaryOp::Eq, &samples[start])?;
+        if !same {
+            if i - start > best_count {
+                best = start;
+                best_count = i - start;
+            }
+            start = i;
+        }
+    }
+    Ok(samples[best].clone())
+}
+/// The sample variance, dividing by `n - 1`.
+pub fn variance(samples: Vec<Number>, settings: &Settings) -> Result<Number, ParserError> {
+    if samples.len() < 2 {
+        return Err(ParserError::newr(
+            "".to_string(),
+            "variance expects at least 2 values".to_string(),
+        ));
+    }
+    let n_minus_1 = op(BinaryOp::Sub, count(&samples), Number::I32(1), settings)?;
+    let mean = mean(samples.clone(), settings)?;
+    let mut total = Number::I32(0);
+    for sample in samples {
+        let deviation = op(BinaryOp::Sub, sample, mean.clone(), settings)?;
+        let square = op(BinaryOp::Mul, deviation.clone(), deviation, settings)?;
+        total = op(BinaryOp::Add, total, square, settings)?;
+    }
+    op(BinaryOp::Div, total, n_minus_1, settings)
+}
+/// The value below which `p` percent of the samples are, interpolating linearly
+/// between the two closest ones. The interpolation divides following the mode, so
+/// that it gives a decimal in decimal mode.
+pub fn percentile(
+    samples: Vec<Number>,
+    p: Number,
+    settings: &Settings,
+) -> Result<Number, ParserError> {
+    if !(0.0..=100.0).contains(&p.as_f64()) {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!(
+                "percentile expects a percentage between 0 and 100, found {}",
+                p
+            ),
+        ));
+    }
+    let samples = sorted(samples);
+    // The rank of the percentile among the samples is `rank / 100`.
+    let n_minus_1 = Number::from_bigint((samples.len() - 1).into(), 32, false);
+    let rank = op(BinaryOp::Mul, p, n_minus_1, settings)?;
+    let lower = op(BinaryOp::FloorDiv, rank.clone(), Number::I32(100), settings)?.as_f64() as usize;
+    let fraction = op(BinaryOp::Mod, rank, Number::I32(100), settings)?;
+    if fraction.is_zero() {
+        return Ok(samples[lower].clone());
+    }
+    let (low, high) = (samples[lower].clone(), samples[lower + 1].clone());
+    let delta = op(BinaryOp::Sub, high, low.clone(), settings)?;
+    let delta = op(BinaryOp::Mul, delta, fraction, settings)?;
+    let delta = op(BinaryOp::Div, delta, Number::I32(100), settings)?;
+    op(BinaryOp::Add, low, delta, settings)
+}
+/// Least squares line through the points `(xs[i], ys[i])`: its slope, its
+/// intercept and the coefficient of determination r².
+pub fn linreg(xs: Vec<Number>, ys: Vec<Number>) -> Result<Literal, ParserError> {
+    if xs.len() != ys.len() {
+        return Err(ParserError::newr(
+            "".to_string(),
+            format!(
+                "linreg expects lists of the same length, found {} and {}",
+                xs.len(),
+                ys.len()
+            ),
+        ));
+    }
+    let xs: Vec<f64> = xs.iter().map(Number::as_f64).collect();
+    let ys: Vec<f64> = ys.iter().map(Number::as_f64).collect();
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(&ys) {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+        syy += (y - mean_y) * (y - mean_y);
+    }
+    if sxx == 0.0 {
+        return Err(ParserError::newr(
+            "".to_string(),
+            "linreg expects at least 2 distinct x values".to_string(),
+        ));
+    }
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    // A horizontal line fits constant ys perfectly.
+    let r2 = if syy == 0.0 {
+        1.0
+    } else {
+        sxy * sxy / (sxx * syy)
+    };
+    Ok(Literal::List(
+        [slope, intercept, r2]
+            .iter()
+            .map(|num| Literal::Num(Number::F64(*num)))
+            .collect(),
+    ))
+}
+
+mod test {
+    use crate::error::ParserError;
+    use crate::eval_line;
+    use crate::math::{Literal, Number};
+    use crate::session::Session;
+
+    #[test]
+    fn statistics() {
+        let mut session = Session::new();
+        eval_line("let xs = [4, 1, 3, 2, 5];".to_string(), &mut session).unwrap();
+        let cases = vec![
+            ("mean(xs)", Number::F64(3.)),
+            ("median(xs)", Number::I32(3)),
+            ("median([4, 1, 3, 2])", Number::F64(2.5)),
+            ("mode([3, 1, 3, 2, 1])", Number::I32(1)),
+            ("mode([2.5, 7, 2.5])", Number::F64(2.5)),
+            ("variance(xs)", Number::F64(2.5)),
+            (
+                "stddev([2, 4, 4, 4, 5, 5, 7, 9])",
+                Number::F64((32f64 / 7.).sqrt()),
+            ),
+            ("percentile(xs, 0)", Number::I32(1)),
+            ("percentile(xs, 50)", Number::I32(3)),
+            ("percentile(xs, 100)", Number::I32(5)),
+            ("percentile(xs, 90)", Number::F64(4.6)),
+            ("sum(xs)", Number::I32(15)),
+            ("prod(xs)", Number::I32(120)),
+            ("sum([])", Number::I32(0)),
+            ("count(xs)", Number::I32(5)),
+            ("count([])", Number::I32(0)),
+            ("min(xs)", Number::I32(1)),
+            ("max(xs)", Number::I32(5)),
+            ("max([2, 7.5, 3])", Number::F64(7.5)),
+        ];
+        for (line, result) in cases {
+            assert_eq!(
+                eval_line(line.to_string(), &mut session),
+                Ok(("".to_string(), Literal::Num(result))),
+                "{}",
+                line
+            );
+        }
+        let (_, fit) = eval_line(
+            "linreg([1, 2, 3, 4], [3, 5, 7, 9])".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(format!("{}", fit), "[2.0, 1.0, 1.0]");
+        let (_, fit) = eval_line("linreg([1, 2, 3], [1, 3, 2])".to_string(), &mut session).unwrap();
+        assert_eq!(format!("{}", fit), "[0.5, 1.0, 0.25]");
+        // The intermediate values of the statistics aren't subject to strict mode.
+        session.run_command(":strict on".to_string()).unwrap();
+        let cases = vec![
+            ("variance([1, 2, 4])", "2.333333333333333"),
+            ("stddev([1, 2, 4])", "1.527525231651947"),
+            ("percentile([1, 2, 3, 4, 5], 90)", "4.6"),
+            ("mean([1.5, 2.5])", "2.0"),
+            ("median([1.5, 2.5])", "2.0"),
+            ("sum([1.5, 2.5])", "4.0"),
+            ("prod([1.5, 2.0])", "3.0"),
+        ];
+        for (line, result) in cases {
+            let (_, value) = eval_line(line.to_string(), &mut session).unwrap();
+            assert_eq!(format!("{:.16}", value), result, "{}", line);
+        }
+        session.run_command(":strict off".to_string()).unwrap();
+        session.run_command(":decimal 4".to_string()).unwrap();
+        let (_, value) =
+            eval_line("percentile([1, 2, 3, 4, 5], 90)".to_string(), &mut session).unwrap();
+        assert!(
+            matches!(value, Literal::Num(Number::Decimal(_))),
+            "{:?}",
+            value
+        );
+        assert_eq!(format!("{}", value), "4.6");
+        let (_, value) = eval_line(
+            "percentile([1, 2, 3, 4, 5], 12.5)".to_string(),
+            &mut session,
+        )
+        .unwrap();
+        assert_eq!(format!("{}", value), "1.5");
+        session.run_command(":decimal off".to_string()).unwrap();
+        session.run_command(":exact on".to_string()).unwrap();
+        assert_eq!(
+            eval_line("mean([1, 2, 2])".to_string(), &mut session)
+                .map(|(_, mean)| format!("{}", mean)),
+            Ok("5/3".to_string())
+        );
+        assert_eq!(
+            eval_line("mean([])".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "mean expects a non empty list".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_line("linreg([1, 2], [1, 2, 3])".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "linreg expects lists of the same length, found 2 and 3".to_string()
+            ))
+        );
+        assert!(eval_line("variance([1])".to_string(), &mut session).is_err());
+        assert!(eval_line("percentile(xs, 101)".to_string(), &mut session).is_err());
+        assert!(eval_line("median([1i, 2])".to_string(), &mut session).is_err());
+        assert_eq!(
+            eval_line("stddev([1i, 2])".to_string(), &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "stddev expects real numbers, found 1i".to_string()
+            ))
+        );
+        assert!(eval_line("linreg([1i, 2], [1, 2])".to_string(), &mut session).is_err());
+        assert!(eval_line("mean([1, 2i])".to_string(), &mut session).is_err());
+        assert!(eval_line("mean([1, true])".to_string(), &mut session).is_err());
+        assert!(eval_line("linreg([1, 1], [1, 2])".to_string(), &mut session).is_err());
+        assert!(eval_line("mean(1)".to_string(), &mut session).is_err());
+        // Sorting more than 20 samples with a nan among them used to panic.
+        let line = format!("median([nan, {}])", ["37, 74"; 12].join(", "));
+        assert_eq!(
+            eval_line(line, &mut session),
+            Err(ParserError::newr(
+                "".to_string(),
+                "median expects numbers, found NaN".to_string()
+            ))
+        );
+        assert!(eval_line("mode([1, nan, 1])".to_string(), &mut session).is_err());
+        assert!(eval_line("percentile([nan, 1], 50)".to_string(), &mut session).is_err());
+    }
+}